@@ -1,9 +1,10 @@
 //! Constants used in rCore for qemu
 
 pub const CLOCK_FREQ: usize = 12500000;
+pub const MEMORY_START: usize = 0x8000_0000;
 pub const MEMORY_END: usize = 0x8800_0000;
 
-#[allow(unused)]
 pub const MMIO: &[(usize, usize)] = &[
     (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
+    (0x1000_1000, 0x00_1000), // Virtio Block in virt machine
 ];
\ No newline at end of file
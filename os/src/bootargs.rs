@@ -0,0 +1,106 @@
+//! 内核启动参数
+//!
+//! SBI跳转到内核时a1中是设备树（FDT）的物理地址，启动参数是其中/chosen节点的bootargs属性，
+//! 在qemu中通过-append指定。设备树所在的内存之后会被页帧分配器回收，
+//! 所以要在mm::init之前把启动参数复制出来
+
+use crate::sync::UPSafeCell;
+use lazy_static::lazy_static;
+
+// 启动参数最多保存这么多字节，超出的部分被丢弃
+const BOOTARGS_MAX: usize = 256;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+struct BootArgs {
+    buf: [u8; BOOTARGS_MAX],
+    len: usize,
+}
+
+lazy_static! {
+    static ref BOOTARGS: UPSafeCell<BootArgs> = UPSafeCell::new(BootArgs {
+        buf: [0; BOOTARGS_MAX],
+        len: 0,
+    });
+}
+
+/// 从地址为dtb的设备树中读出启动参数，没有设备树或者没有bootargs属性时启动参数为空
+pub fn init(dtb: usize) {
+    if let Some(args) = unsafe { find_bootargs(dtb) } {
+        let mut bootargs = BOOTARGS.exclusive_access();
+        let len = args.len().min(BOOTARGS_MAX);
+        bootargs.buf[..len].copy_from_slice(&args[..len]);
+        bootargs.len = len;
+    }
+}
+
+/// 启动参数中是否有arg这一项，各项之间用空白分隔
+pub fn has_bootarg(arg: &str) -> bool {
+    let bootargs = BOOTARGS.exclusive_access();
+    core::str::from_utf8(&bootargs.buf[..bootargs.len])
+        .map_or(false, |args| args.split_whitespace().any(|a| a == arg))
+}
+
+/// 读设备树中大端序的32位整数
+unsafe fn read_be32(addr: usize) -> u32 {
+    u32::from_be((addr as *const u32).read_volatile())
+}
+
+/// 在设备树的结构块中找到/chosen节点的bootargs属性，返回去掉结尾'\0'的内容
+unsafe fn find_bootargs(dtb: usize) -> Option<&'static [u8]> {
+    if dtb == 0 || dtb % 4 != 0 || read_be32(dtb) != FDT_MAGIC {
+        return None;
+    }
+    let struct_start = dtb + read_be32(dtb + 8) as usize;
+    let strings_start = dtb + read_be32(dtb + 12) as usize;
+    let struct_end = struct_start + read_be32(dtb + 36) as usize;
+
+    let mut p = struct_start;
+    // 当前节点的深度，根节点为1；in_chosen表示当前正在根节点下的chosen节点中
+    let mut depth = 0;
+    let mut in_chosen = false;
+    while p < struct_end {
+        let token = read_be32(p);
+        p += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = c_str(p);
+                // 节点名以'\0'结尾并补齐到4字节
+                p += (name.len() + 1 + 3) & !3;
+                depth += 1;
+                in_chosen = depth == 2 && (name == b"chosen" || name.starts_with(b"chosen@"));
+            }
+            FDT_END_NODE => {
+                depth -= 1;
+                in_chosen = false;
+            }
+            FDT_PROP => {
+                let len = read_be32(p) as usize;
+                let name_off = read_be32(p + 4) as usize;
+                let value = p + 8;
+                p = value + ((len + 3) & !3);
+                if in_chosen && c_str(strings_start + name_off) == b"bootargs" {
+                    let args = core::slice::from_raw_parts(value as *const u8, len);
+                    return Some(args.split(|&b| b == 0).next().unwrap_or(&[]));
+                }
+            }
+            FDT_NOP => {}
+            // FDT_END或者无法识别的token
+            _ => break,
+        }
+    }
+    None
+}
+
+/// 地址start处以'\0'结尾的字符串，不包括'\0'
+unsafe fn c_str(start: usize) -> &'static [u8] {
+    let mut end = start;
+    while (end as *const u8).read_volatile() != 0 {
+        end += 1;
+    }
+    core::slice::from_raw_parts(start as *const u8, end - start)
+}
@@ -1,6 +1,34 @@
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+// 内核堆的初始大小，用完之后再从页帧分配器拿页帧扩充
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+// 内核堆每次至少扩充的页帧数
+pub const KERNEL_HEAP_GROW_PAGES: usize = 0x100; // 1M
+// 给内核堆预留的页帧数，页帧分配器分配不出来或者正被占用时用它们扩充内核堆
+pub const KERNEL_HEAP_RESERVE_PAGES: usize = 0x100; // 1M
+// 用户栈初始的大小，之后缺页时向下增长，最多增长到USER_STACK_LIMIT
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+pub const USER_STACK_LIMIT: usize = 0x10_0000; // 1M
+// 用户栈增长上限下方的保护区间，访问这里的缺页都当作栈溢出
+pub const STACK_GUARD_GAP: usize = 0x10_0000; // 1M
+// exec的参数和环境变量（包括字符串和指针）总共最多占用的字节数，它们都放在初始的用户栈上
+pub const ARG_MAX: usize = USER_STACK_SIZE / 4;
+// 用户堆最多能通过sbrk增长到的大小，堆区和用户栈之间会预留出这么大的虚拟地址空间
+pub const USER_HEAP_LIMIT: usize = 0x80_0000; // 8M
+// mmap使用的虚拟地址区间，位于用户栈之上，终点是Sv39低半部分地址空间的尽头
+pub const USER_MMAP_BASE: usize = 0x10_0000_0000;
+pub const USER_MMAP_END: usize = 0x40_0000_0000;
+// 开启ASLR时，用户堆和用户栈的起始地址分别随机后移最多这么多页
+pub const ASLR_HEAP_PAGES: usize = 0x1000; // 16M
+pub const ASLR_STACK_PAGES: usize = 0x4000; // 64M
+// 位置无关的可执行文件（PIE）的加载地址，开启ASLR时再随机后移最多ASLR_PIE_PAGES页
+pub const PIE_BASE: usize = 0x4000_0000;
+pub const ASLR_PIE_PAGES: usize = 0x4_0000; // 1G
+// 交换区能容纳的页面数，Makefile中创建的交换盘镜像大小要与之一致
+pub const SWAP_PAGES: usize = 0x4000; // 64M
+// 空闲物理页帧少于这个数时开始把用户页面换出
+pub const SWAP_LOW_WATERMARK: usize = 0x100;
+// 每次从一个进程中最多换出的页面数
+pub const SWAP_BATCH: usize = 0x40;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc; // 12
 
@@ -16,5 +44,5 @@ pub const TRAP_CONTEXT_ADDRESS: usize = TRAMPOLINE_ADDRESS - PAGE_SIZE;
 // pub const APP_BASE_ADDRESS: usize = 0x80400000;
 // pub const APP_SIZE_LIMIT: usize = 0x20000;
 
-pub use crate::board::{CLOCK_FREQ, MEMORY_END};
+pub use crate::board::{CLOCK_FREQ, MEMORY_END, MEMORY_START, MMIO};
 
@@ -0,0 +1,20 @@
+mod virtio_blk;
+
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+
+// 块设备一次读写的大小
+pub const BLOCK_SZ: usize = 512;
+
+/// 块设备接口，以块为单位读写，buf的长度为BLOCK_SZ
+pub trait BlockDevice: Send + Sync {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
+
+type BlockDeviceImpl = virtio_blk::VirtIOBlock;
+
+// 全局的块设备，第一次使用时初始化
+lazy_static! {
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+}
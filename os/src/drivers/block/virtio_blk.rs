@@ -0,0 +1,77 @@
+use super::BlockDevice;
+use crate::mm::{
+    frame_alloc_contiguous, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum, VirtAddr,
+};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
+
+// qemu virt机器上第一个virtio-mmio设备的地址
+const VIRTIO0: usize = 0x1000_1000;
+
+pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
+
+// virtio队列使用的物理页帧，在dma_dealloc之前一直持有
+lazy_static! {
+    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = UPSafeCell::new(Vec::new());
+}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.0
+            .exclusive_access()
+            .read_block(block_id, buf)
+            .expect("Error when reading VirtIOBlk");
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.0
+            .exclusive_access()
+            .write_block(block_id, buf)
+            .expect("Error when writing VirtIOBlk");
+    }
+}
+
+impl VirtIOBlock {
+    pub fn new() -> Self {
+        unsafe {
+            Self(UPSafeCell::new(
+                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
+            ))
+        }
+    }
+}
+
+pub struct VirtioHal;
+
+impl Hal for VirtioHal {
+    /// 分配pages个物理上连续的页帧
+    fn dma_alloc(pages: usize) -> usize {
+        let frames = frame_alloc_contiguous(pages).unwrap();
+        let pa: PhysAddr = frames[0].ppn.into();
+        QUEUE_FRAMES.exclusive_access().extend(frames);
+        pa.0
+    }
+
+    fn dma_dealloc(pa: usize, pages: usize) -> i32 {
+        let ppn_base: PhysPageNum = PhysAddr::from(pa).floor();
+        // FrameTracker被drop时页帧就回收了
+        QUEUE_FRAMES
+            .exclusive_access()
+            .retain(|frame| frame.ppn.0 < ppn_base.0 || frame.ppn.0 >= ppn_base.0 + pages);
+        0
+    }
+
+    // 内核中物理内存是恒等映射的
+    fn phys_to_virt(addr: usize) -> usize {
+        addr
+    }
+
+    fn virt_to_phys(vaddr: usize) -> usize {
+        PageTable::from_token(kernel_token())
+            .translate_va(VirtAddr::from(vaddr))
+            .unwrap()
+            .0
+    }
+}
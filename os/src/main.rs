@@ -8,12 +8,15 @@ use core::arch::global_asm;
 // macro_use用来将console的宏导出来 下面的lang_items也能用print和println
 #[macro_use]
 mod sync;
+mod bootargs;
 mod config;
 mod console;
+mod drivers;
 mod lang_items;
 mod loader;
 mod logging;
 mod mm;
+mod random;
 mod sbi;
 mod syscall;
 mod task;
@@ -28,11 +31,15 @@ global_asm!(include_str!("link_app.S"));
 
 // 默认情况 rust编译器会对每个函数进行名称修饰(name mangling) 保证每个函数都有唯一的名字 以支持重载等特性
 // 使用#[no_mangle]属性修饰 可以保证rust_main在汇编语言中的标签就是rust_main
+// SBI跳转到内核时a0为hart id，a1为设备树的物理地址，entry.asm没有改动它们
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(_hart_id: usize, dtb: usize) -> ! {
     println!("clear bss...");
     clear_bss();
 
+    // 设备树所在的内存会被页帧分配器回收，要在mm::init之前读出启动参数
+    bootargs::init(dtb);
+
     println!("mm init...");
     mm::init();
 
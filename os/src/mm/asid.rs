@@ -0,0 +1,98 @@
+//! 地址空间标识符（ASID）：TLB中的页表项带有ASID标签，
+//! 切换到ASID不同的地址空间时不需要刷新整个TLB
+
+use crate::println;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use core::arch::asm;
+use lazy_static::lazy_static;
+use riscv::register::satp;
+
+// satp中ASID字段的位置，Sv39最多支持16位ASID
+const ASID_SHIFT: usize = 44;
+const ASID_MASK: usize = 0xffff;
+
+// ASID 0表示没有独占的ASID，切换到这样的地址空间时要刷新整个TLB
+// 硬件不支持ASID或者ASID用完了时使用
+struct AsidAllocator {
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl AsidAllocator {
+    fn alloc(&mut self) -> usize {
+        if let Some(asid) = self.recycled.pop() {
+            asid
+        } else if self.current < self.end {
+            self.current += 1;
+            self.current - 1
+        } else {
+            0
+        }
+    }
+
+    fn dealloc(&mut self, asid: usize) {
+        assert!(
+            asid < self.current && !self.recycled.contains(&asid),
+            "ASID {} has not been allocated!",
+            asid
+        );
+        self.recycled.push(asid);
+    }
+}
+
+lazy_static! {
+    // init_asid之前end为1，只会分配出ASID 0
+    static ref ASID_ALLOCATOR: UPSafeCell<AsidAllocator> = UPSafeCell::new(AsidAllocator {
+        current: 1,
+        end: 1,
+        recycled: Vec::new(),
+    });
+}
+
+/// 检测硬件支持的ASID位数，要在开启分页之后调用
+pub fn init_asid() {
+    // 往ASID字段写全1再读回来，能保留下来的位就是硬件支持的位
+    let old = satp::read().bits();
+    let asid_bits = unsafe {
+        satp::write(old | ASID_MASK << ASID_SHIFT);
+        let asid_max = (satp::read().bits() >> ASID_SHIFT) & ASID_MASK;
+        satp::write(old);
+        asm!("sfence.vma");
+        asid_max.count_ones()
+    };
+    ASID_ALLOCATOR.exclusive_access().end = 1 << asid_bits;
+    println!("[kernel] ASID: {} bits supported", asid_bits);
+}
+
+/// RAII的ASID，drop时自动回收
+pub struct AsidHandle(pub usize);
+
+impl Drop for AsidHandle {
+    fn drop(&mut self) {
+        if self.0 != 0 {
+            ASID_ALLOCATOR.exclusive_access().dealloc(self.0);
+        }
+    }
+}
+
+/// 分配一个ASID，用完了时返回ASID 0
+pub fn asid_alloc() -> AsidHandle {
+    let asid = ASID_ALLOCATOR.exclusive_access().alloc();
+    if asid != 0 {
+        // 回收的ASID在TLB中可能还留有之前的地址空间的页表项
+        unsafe { asm!("sfence.vma x0, {}", in(reg) asid) };
+    }
+    AsidHandle(asid)
+}
+
+/// 从satp中取出ASID
+pub fn token_asid(token: usize) -> usize {
+    (token >> ASID_SHIFT) & ASID_MASK
+}
+
+/// 把ASID放到satp对应的字段中
+pub fn asid_token(asid: usize) -> usize {
+    (asid & ASID_MASK) << ASID_SHIFT
+}
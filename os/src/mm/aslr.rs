@@ -0,0 +1,28 @@
+//! 用户地址空间布局随机化（ASLR）
+//!
+//! 开启时用户栈、用户堆的起始地址以及PIE的加载地址都会随机后移若干页。
+//! 启动参数中有aslr=off（或aslr=0）时关闭，`make run ASLR=off`会通过qemu的-append传入，
+//! 方便调试时复现地址
+
+use crate::bootargs::has_bootarg;
+use crate::config::PAGE_SIZE;
+use crate::println;
+use crate::random::random_u64;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static ASLR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn init_aslr() {
+    let enabled = !has_bootarg("aslr=off") && !has_bootarg("aslr=0");
+    ASLR_ENABLED.store(enabled, Ordering::Relaxed);
+    println!("[kernel] ASLR {}", if enabled { "on" } else { "off" });
+}
+
+/// 开启ASLR时返回[0, max_pages)中随机的页数对应的字节数，关闭时返回0
+pub fn aslr_offset(max_pages: usize) -> usize {
+    if ASLR_ENABLED.load(Ordering::Relaxed) {
+        (random_u64() as usize % max_pages) * PAGE_SIZE
+    } else {
+        0
+    }
+}
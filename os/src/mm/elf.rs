@@ -0,0 +1,364 @@
+//! ELF加载时用到的辅助函数
+//!
+//! 用户程序可以是普通的可执行文件（ET_EXEC），也可以是位置无关的可执行文件（ET_DYN），
+//! 后者整体加载到load_bias之上，再按PT_DYNAMIC中的R_RISCV_RELATIVE重定位修正其中的地址。
+//! 文件内容来自用户，加载之前要先检查，不合法时返回ElfError而不是让内核panic
+
+use super::address::VirtAddr;
+use super::memory_set::MapPermission;
+use crate::config::{PAGE_SIZE, USER_MMAP_BASE, USER_STACK_SIZE};
+use crate::println;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use xmas_elf::dynamic::Tag;
+use xmas_elf::header::{Class, Type as ElfType};
+use xmas_elf::program::{ProgramHeader, ProgramHeader64, SegmentData, Type};
+use xmas_elf::ElfFile;
+
+/// 文件头中e_machine的偏移和RISC-V对应的值
+const E_MACHINE_OFFSET: usize = 18;
+const EM_RISCV: u16 = 0xf3;
+/// PT_GNU_STACK，它的标志位决定用户栈是否可执行
+const PT_GNU_STACK: u32 = 0x6474_e551;
+const R_RISCV_NONE: u64 = 0;
+const R_RISCV_RELATIVE: u64 = 3;
+/// 一个Elf64_Rela的大小
+const RELA_SIZE: usize = 24;
+
+/// 辅助向量（auxv）中各项的类型，和Linux一致
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_ENTRY: usize = 9;
+pub const AT_RANDOM: usize = 25;
+
+/// ELF文件不能加载的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// 文件头或程序头表无法解析
+    Malformed,
+    /// 不是64位的ELF文件
+    BadClass,
+    /// 不是RISC-V的程序
+    BadMachine,
+    /// 既不是ET_EXEC也不是ET_DYN
+    BadType,
+    /// 没有PT_LOAD段
+    NoSegments,
+    /// 段的内容超出了文件，或者file_size大于mem_size
+    BadSegment,
+    /// PT_LOAD段的虚拟地址和文件偏移在页内的偏移不同，无法按页映射
+    MisalignedSegment,
+    /// 段超出了用户程序可用的地址范围
+    SegmentOutOfRange,
+    /// 两个PT_LOAD段落在同一个页面中
+    OverlappingSegments,
+    /// 入口地址不在可执行的段中
+    BadEntry,
+    /// 重定位表不合法或者有不支持的重定位类型
+    BadRelocation,
+    /// 文件本身没有问题，但加载时物理内存不足
+    NoMemory,
+}
+
+/// from_elf解析出的程序信息
+pub struct ElfInfo {
+    /// 用户栈的基地址
+    pub ustack_base: usize,
+    /// 用户堆的基地址
+    pub heap_bottom: usize,
+    pub entry_point: usize,
+    /// 用户栈的权限，由PT_GNU_STACK决定
+    pub stack_perm: MapPermission,
+    /// PT_TLS描述的线程局部存储
+    pub tls: Option<TlsTemplate>,
+    /// 程序头表在用户地址空间中的地址，没有被加载时为0
+    pub phdr: usize,
+    pub phnum: usize,
+}
+
+impl ElfInfo {
+    /// 由程序本身决定的辅助向量，AT_RANDOM和AT_NULL在放到栈上时再加
+    pub fn auxv(&self) -> [(usize, usize); 4] {
+        [
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_PHDR, self.phdr),
+            (AT_PHNUM, self.phnum),
+            (AT_ENTRY, self.entry_point),
+        ]
+    }
+}
+
+/// 线程局部存储的初始内容，每个线程都要复制一份
+#[derive(Clone)]
+pub struct TlsTemplate {
+    /// .tdata的内容，之后到mem_size为止的.tbss都是0
+    pub image: Vec<u8>,
+    pub mem_size: usize,
+    pub align: usize,
+}
+
+/// 一条R_RISCV_RELATIVE重定位：往offset处写入load_bias + addend
+pub struct Relocation {
+    pub offset: usize,
+    pub addend: usize,
+}
+
+fn is_load(ph: &ProgramHeader) -> bool {
+    ph.get_type() == Ok(Type::Load)
+}
+
+/// 检查ELF文件能否加载，load_bias加上段的地址后要落在用户程序可用的范围内
+pub fn validate(elf: &ElfFile, load_bias: usize) -> Result<(), ElfError> {
+    let header = elf.header;
+    if header.pt1.class() != Class::SixtyFour {
+        return Err(ElfError::BadClass);
+    }
+    let machine =
+        u16::from_le_bytes([elf.input[E_MACHINE_OFFSET], elf.input[E_MACHINE_OFFSET + 1]]);
+    if machine != EM_RISCV {
+        return Err(ElfError::BadMachine);
+    }
+    match header.pt2.type_().as_type() {
+        ElfType::Executable | ElfType::SharedObject => {}
+        _ => return Err(ElfError::BadType),
+    }
+    // xmas-elf读程序头时不检查边界，程序头表必须完整地在文件中
+    let ph_entry_size = header.pt2.ph_entry_size() as usize;
+    let ph_table_end = (header.pt2.ph_count() as usize)
+        .checked_mul(ph_entry_size)
+        .and_then(|size| size.checked_add(header.pt2.ph_offset() as usize));
+    if ph_entry_size != size_of::<ProgramHeader64>()
+        || ph_table_end.map_or(true, |end| end > elf.input.len())
+    {
+        return Err(ElfError::Malformed);
+    }
+    // 每个段占用的页面范围
+    let mut ranges = Vec::new();
+    for i in 0..header.pt2.ph_count() {
+        let ph = elf.program_header(i).map_err(|_| ElfError::Malformed)?;
+        if ph.get_type().is_err() {
+            return Err(ElfError::Malformed);
+        }
+        let file_end = ph.offset().checked_add(ph.file_size());
+        if file_end.map_or(true, |end| end > elf.input.len() as u64) {
+            return Err(ElfError::BadSegment);
+        }
+        if ph.get_type() == Ok(Type::Tls) {
+            // 线程局部存储放在每个线程的用户栈上
+            let align = ph.align();
+            if ph.file_size() > ph.mem_size()
+                || ph.mem_size() > USER_STACK_SIZE as u64 / 2
+                || (align != 0 && !align.is_power_of_two())
+                || align > PAGE_SIZE as u64
+            {
+                return Err(ElfError::BadSegment);
+            }
+        }
+        if !is_load(&ph) {
+            continue;
+        }
+        if ph.file_size() > ph.mem_size() {
+            return Err(ElfError::BadSegment);
+        }
+        if ph.virtual_addr() % PAGE_SIZE as u64 != ph.offset() % PAGE_SIZE as u64 {
+            return Err(ElfError::MisalignedSegment);
+        }
+        let start = (ph.virtual_addr() as usize).checked_add(load_bias);
+        let end = start.and_then(|start| start.checked_add(ph.mem_size() as usize));
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if end <= USER_MMAP_BASE => (start, end),
+            _ => return Err(ElfError::SegmentOutOfRange),
+        };
+        let range = (VirtAddr::from(start).floor(), VirtAddr::from(end).ceil());
+        if ranges
+            .iter()
+            .any(|&(other_start, other_end)| range.0 < other_end && other_start < range.1)
+        {
+            return Err(ElfError::OverlappingSegments);
+        }
+        ranges.push(range);
+    }
+    if ranges.is_empty() {
+        return Err(ElfError::NoSegments);
+    }
+    let entry = header.pt2.entry_point();
+    let entry_ok = elf.program_iter().filter(is_load).any(|ph| {
+        ph.flags().is_execute()
+            && ph.virtual_addr() <= entry
+            && ph
+                .virtual_addr()
+                .checked_add(ph.mem_size())
+                .map_or(false, |end| entry < end)
+    });
+    if !entry_ok {
+        return Err(ElfError::BadEntry);
+    }
+    Ok(())
+}
+
+/// 把程序中的虚拟地址转换成文件中的偏移，只有PT_LOAD中来自文件的部分才有对应的偏移
+fn vaddr_to_offset(elf: &ElfFile, vaddr: usize) -> Option<usize> {
+    elf.program_iter()
+        .filter(is_load)
+        .find(|ph| {
+            let start = ph.virtual_addr() as usize;
+            start <= vaddr
+                && start
+                    .checked_add(ph.file_size() as usize)
+                    .map_or(false, |end| vaddr < end)
+        })
+        .map(|ph| ph.offset() as usize + vaddr - ph.virtual_addr() as usize)
+}
+
+/// 读取PT_DYNAMIC中DT_RELA指向的重定位表
+/// 用户程序是静态链接的，只会有R_RISCV_RELATIVE
+pub fn relocations(elf: &ElfFile) -> Result<Vec<Relocation>, ElfError> {
+    let mut relocations = Vec::new();
+    let dynamic = match elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Dynamic))
+        .map(|ph| ph.get_data(elf))
+    {
+        None => return Ok(relocations),
+        Some(Ok(SegmentData::Dynamic64(dynamic))) => dynamic,
+        Some(_) => return Err(ElfError::BadRelocation),
+    };
+    let (mut rela, mut rela_size, mut rela_ent) = (0, 0, RELA_SIZE as u64);
+    for entry in dynamic {
+        match entry.get_tag() {
+            Ok(Tag::Rela) => rela = entry.get_ptr().map_err(|_| ElfError::BadRelocation)?,
+            Ok(Tag::RelaSize) => {
+                rela_size = entry.get_val().map_err(|_| ElfError::BadRelocation)?
+            }
+            Ok(Tag::RelaEnt) => rela_ent = entry.get_val().map_err(|_| ElfError::BadRelocation)?,
+            Ok(Tag::Null) => break,
+            _ => {}
+        }
+    }
+    if rela_size == 0 {
+        return Ok(relocations);
+    }
+    if rela_ent != RELA_SIZE as u64 {
+        return Err(ElfError::BadRelocation);
+    }
+    let table = vaddr_to_offset(elf, rela as usize)
+        .and_then(|start| elf.input.get(start..start.checked_add(rela_size as usize)?))
+        .ok_or(ElfError::BadRelocation)?;
+    let read_u64 = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap());
+    for entry in table.chunks_exact(RELA_SIZE) {
+        match read_u64(&entry[8..16]) & 0xffff_ffff {
+            R_RISCV_NONE => {}
+            R_RISCV_RELATIVE => relocations.push(Relocation {
+                offset: read_u64(&entry[0..8]) as usize,
+                addend: read_u64(&entry[16..24]) as usize,
+            }),
+            _ => return Err(ElfError::BadRelocation),
+        }
+    }
+    Ok(relocations)
+}
+
+/// 复制一段从vaddr开始的文件内容，并修正落在其中的重定位
+pub fn relocate(
+    data: &[u8],
+    vaddr: usize,
+    relocations: &[Relocation],
+    load_bias: usize,
+) -> Vec<u8> {
+    let mut data = data.to_vec();
+    for reloc in relocations {
+        let end = reloc.offset.checked_add(8);
+        if reloc.offset >= vaddr && end.map_or(false, |end| end <= vaddr + data.len()) {
+            let start = reloc.offset - vaddr;
+            let value = load_bias.wrapping_add(reloc.addend) as u64;
+            data[start..start + 8].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+    data
+}
+
+/// 程序头表在用户地址空间中的地址
+/// 有PT_PHDR时直接用它，否则找到包含程序头表的PT_LOAD段，都没有时返回0
+pub fn phdr_address(elf: &ElfFile, load_bias: usize) -> usize {
+    if let Some(ph) = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Phdr))
+    {
+        return load_bias + ph.virtual_addr() as usize;
+    }
+    let header = &elf.header.pt2;
+    let start = header.ph_offset();
+    let end = start + header.ph_count() as u64 * header.ph_entry_size() as u64;
+    elf.program_iter()
+        .filter(is_load)
+        .find(|ph| ph.offset() <= start && end <= ph.offset() + ph.file_size())
+        .map_or(0, |ph| {
+            load_bias + (ph.virtual_addr() + start - ph.offset()) as usize
+        })
+}
+
+/// 根据PT_GNU_STACK决定用户栈的权限，默认不可执行
+pub fn stack_permission(elf: &ElfFile) -> MapPermission {
+    let mut perm = MapPermission::R | MapPermission::W | MapPermission::U;
+    let executable = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::OsSpecific(PT_GNU_STACK)))
+        .map_or(false, |ph| ph.flags().is_execute());
+    if executable {
+        perm |= MapPermission::X;
+    }
+    perm
+}
+
+/// 读取PT_TLS描述的线程局部存储模板
+pub fn tls_template(
+    elf: &ElfFile,
+    relocations: &[Relocation],
+    load_bias: usize,
+) -> Option<TlsTemplate> {
+    let ph = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Tls))?;
+    let offset = ph.offset() as usize;
+    let image = &elf.input[offset..offset + ph.file_size() as usize];
+    Some(TlsTemplate {
+        image: relocate(image, ph.virtual_addr() as usize, relocations, load_bias),
+        mem_size: ph.mem_size() as usize,
+        align: (ph.align() as usize).max(1),
+    })
+}
+
+#[allow(unused)]
+pub fn elf_test(elf_data: &[u8]) {
+    use super::memory_set::MemorySet;
+    let load = |data: &[u8]| MemorySet::from_elf(data).err();
+    let read_u64 = |data: &[u8], offset: usize| {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    };
+    assert_eq!(load(elf_data), None);
+    assert_eq!(load(&elf_data[..10]), Some(ElfError::Malformed));
+
+    let mut data = elf_data.to_vec();
+    data[E_MACHINE_OFFSET] = 0x3e;
+    assert_eq!(load(&data), Some(ElfError::BadMachine));
+
+    // e_entry
+    let mut data = elf_data.to_vec();
+    data[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(load(&data), Some(ElfError::BadEntry));
+
+    // 第一个PT_LOAD的p_filesz改成比p_memsz大
+    let mut data = elf_data.to_vec();
+    let ph_offset = read_u64(&data, 32) as usize;
+    let ph_size = u16::from_le_bytes([data[54], data[55]]) as usize;
+    let load_ph = (0..)
+        .map(|i| ph_offset + i * ph_size)
+        .find(|&ph| data[ph..ph + 4] == 1u32.to_le_bytes())
+        .unwrap();
+    let mem_size = read_u64(&data, load_ph + 40);
+    data[load_ph + 32..load_ph + 40].copy_from_slice(&(mem_size + 1).to_le_bytes());
+    assert_eq!(load(&data), Some(ElfError::BadSegment));
+    println!("elf_test passed!");
+}
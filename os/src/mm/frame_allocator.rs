@@ -1,5 +1,5 @@
 use super::address::PhysPageNum;
-use alloc::vec::Vec;
+use alloc::{collections::BTreeSet, vec, vec::Vec};
 use core::fmt::{Debug, Formatter};
 
 // 从ekernel到MEMORY_END的内存可以分配出去
@@ -7,9 +7,17 @@ trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    /// 分配pages个物理上连续的页帧，返回第一个页帧的物理页号
+    /// 分配出去的页帧之后逐个dealloc
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum>;
+    /// 剩余可分配的页帧数
+    fn free_frames(&self) -> usize;
+    /// 管理的页帧总数
+    fn total_frames(&self) -> usize;
 }
 
 pub struct StackFrameAllocator {
+    start: usize,   //管理的内存的起始物理页号
     current: usize, //空闲内存的起始物理页号
     end: usize,     //空闲内存的结束物理页号
     recycled: Vec<usize>,
@@ -18,6 +26,7 @@ pub struct StackFrameAllocator {
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
         StackFrameAllocator {
+            start: 0,
             current: 0,
             end: 0,
             recycled: Vec::new(),
@@ -52,6 +61,24 @@ impl FrameAllocator for StackFrameAllocator {
 
         self.recycled.push(ppn);
     }
+
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        // 回收的页帧不一定连续，只能从还没有分配过的部分中分配
+        if self.current + pages <= self.end {
+            self.current += pages;
+            Some((self.current - pages).into())
+        } else {
+            None
+        }
+    }
+
+    fn free_frames(&self) -> usize {
+        self.end - self.current + self.recycled.len()
+    }
+
+    fn total_frames(&self) -> usize {
+        self.end - self.start
+    }
 }
 
 impl StackFrameAllocator {
@@ -60,14 +87,143 @@ impl StackFrameAllocator {
             "[kernel] FrameAllocator: init with start: {:#x}, end: {:#x}",
             start.0, end.0
         );
+        self.start = start.0;
         self.current = start.0;
         self.end = end.0;
     }
 }
 
-use crate::{board::MEMORY_END, mm::address::PhysAddr, println, sync::UPSafeCell};
+// 伙伴系统最大的块为2^(BUDDY_MAX_ORDER - 1)个页帧
+const BUDDY_MAX_ORDER: usize = 20;
+
+/// 伙伴系统页帧分配器
+/// 把空闲内存分成若干个2^k个页帧大小的块，分配时把大块对半拆开，
+/// 回收时如果伙伴块也是空闲的就合并成大块
+pub struct BuddyFrameAllocator {
+    base: usize, // 管理的内存的起始物理页号，块的对齐都是相对base而言的
+    end: usize,
+    // free_lists[k]中保存大小为2^k的空闲块相对base的偏移
+    free_lists: Vec<BTreeSet<usize>>,
+    free: usize,
+}
+
+impl BuddyFrameAllocator {
+    pub fn init(&mut self, start: PhysPageNum, end: PhysPageNum) {
+        println!(
+            "[kernel] BuddyFrameAllocator: init with start: {:#x}, end: {:#x}",
+            start.0, end.0
+        );
+        self.base = start.0;
+        self.end = end.0;
+        self.free = end.0 - start.0;
+        // 把[start, end)切成尽量大的对齐的块
+        let mut offset = 0;
+        while offset < self.free {
+            let mut order = 0;
+            while order + 1 < BUDDY_MAX_ORDER
+                && offset % (1 << (order + 1)) == 0
+                && offset + (1 << (order + 1)) <= self.free
+            {
+                order += 1;
+            }
+            self.free_lists[order].insert(offset);
+            offset += 1 << order;
+        }
+    }
+
+    /// 分配一个2^order个页帧的块，返回它相对base的偏移
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        // 找到不小于order的最小的空闲块，多出来的一半一半地放回去
+        let mut k = (order..BUDDY_MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let offset = self.free_lists[k].pop_first().unwrap();
+        while k > order {
+            k -= 1;
+            self.free_lists[k].insert(offset + (1 << k));
+        }
+        self.free -= 1 << order;
+        Some(offset)
+    }
+
+    /// 回收偏移为offset、大小为2^order个页帧的块，并尽量和伙伴块合并
+    fn dealloc_block(&mut self, mut offset: usize, mut order: usize) {
+        // 检查这个块和空闲块是否有重叠，有的话说明重复释放了
+        for k in 0..BUDDY_MAX_ORDER {
+            let overlapped = if k >= order {
+                self.free_lists[k].contains(&(offset & !((1 << k) - 1)))
+            } else {
+                self.free_lists[k]
+                    .range(offset..offset + (1 << order))
+                    .next()
+                    .is_some()
+            };
+            if overlapped {
+                panic!(
+                    "Frame ppn={:#x} has not been allocated!",
+                    self.base + offset
+                );
+            }
+        }
+        self.free += 1 << order;
+        while order + 1 < BUDDY_MAX_ORDER && self.free_lists[order].remove(&(offset ^ (1 << order)))
+        {
+            offset &= !(1 << order);
+            order += 1;
+        }
+        self.free_lists[order].insert(offset);
+    }
+}
+
+impl FrameAllocator for BuddyFrameAllocator {
+    fn new() -> Self {
+        Self {
+            base: 0,
+            end: 0,
+            free_lists: (0..BUDDY_MAX_ORDER).map(|_| BTreeSet::new()).collect(),
+            free: 0,
+        }
+    }
+
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        self.alloc_contiguous(1)
+    }
+
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        if ppn.0 < self.base || ppn.0 >= self.end {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn.0);
+        }
+        self.dealloc_block(ppn.0 - self.base, 0);
+    }
+
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        let order = pages.next_power_of_two().trailing_zeros() as usize;
+        let offset = if pages > 0 && order < BUDDY_MAX_ORDER {
+            self.alloc_block(order)
+        } else {
+            None
+        };
+        // 分配失败是正常情况，调用者可能会换出一些页面后再试，这里不打印
+        let offset = offset?;
+        // 块中多出来的页帧直接还回去
+        for i in pages..1 << order {
+            self.dealloc_block(offset + i, 0);
+        }
+        Some((self.base + offset).into())
+    }
+
+    fn free_frames(&self) -> usize {
+        self.free
+    }
+
+    fn total_frames(&self) -> usize {
+        self.end - self.base
+    }
+}
+
+use crate::{
+    board::MEMORY_END, mm::address::PhysAddr, println, sync::UPSafeCell, task::reclaim_frames,
+};
 use lazy_static::lazy_static;
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 // 全局的FrameAllocator
 lazy_static! {
@@ -80,12 +236,45 @@ pub fn init_frame_allocator() {
         fn ekernel();
     }
     // 头需要上取整，尾需要下取整，细节
-    FRAME_ALLOCATOR.exclusive_access().init(
-        PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
-    );
+    let start = PhysAddr::from(ekernel as usize).ceil();
+    let end = PhysAddr::from(MEMORY_END).floor();
+    FRAME_ALLOCATOR.exclusive_access().init(start, end);
+    FRAME_METAS.exclusive_access().init(start, end);
+}
+
+/// 一个物理页帧的元数据
+#[derive(Clone, Copy, Default)]
+struct FrameMeta {
+    /// 指向这个页帧的FrameTracker的个数，为0说明页帧空闲，或者被slab、内核堆直接使用
+    ref_count: usize,
 }
 
+/// 以物理页号为下标的页帧元数据数组，覆盖页帧分配器管理的所有页帧
+struct FrameMetaTable {
+    base: usize,
+    metas: Vec<FrameMeta>,
+}
+
+impl FrameMetaTable {
+    fn init(&mut self, start: PhysPageNum, end: PhysPageNum) {
+        self.base = start.0;
+        self.metas = vec![FrameMeta::default(); end.0 - start.0];
+    }
+
+    fn get_mut(&mut self, ppn: PhysPageNum) -> &mut FrameMeta {
+        &mut self.metas[ppn.0 - self.base]
+    }
+}
+
+lazy_static! {
+    static ref FRAME_METAS: UPSafeCell<FrameMetaTable> = UPSafeCell::new(FrameMetaTable {
+        base: 0,
+        metas: Vec::new(),
+    });
+}
+
+/// 物理页帧的引用计数句柄，clone出来的句柄指向同一个页帧，
+/// 写时复制和共享内存用它在多个地址空间之间共享页帧，最后一个句柄被drop时回收页帧
 pub struct FrameTracker {
     pub ppn: PhysPageNum,
 }
@@ -97,13 +286,37 @@ impl FrameTracker {
         for i in bytes_array {
             *i = 0;
         }
+        let mut metas = FRAME_METAS.exclusive_access();
+        let meta = metas.get_mut(ppn);
+        assert_eq!(meta.ref_count, 0, "frame {:#x} is already in use", ppn.0);
+        meta.ref_count = 1;
         FrameTracker { ppn }
     }
+
+    /// 指向这个页帧的句柄数
+    pub fn ref_count(&self) -> usize {
+        FRAME_METAS.exclusive_access().get_mut(self.ppn).ref_count
+    }
+}
+
+impl Clone for FrameTracker {
+    fn clone(&self) -> Self {
+        FRAME_METAS.exclusive_access().get_mut(self.ppn).ref_count += 1;
+        FrameTracker { ppn: self.ppn }
+    }
 }
 
 impl Drop for FrameTracker {
     fn drop(&mut self) {
-        frame_dealloc(self.ppn);
+        let mut metas = FRAME_METAS.exclusive_access();
+        let meta = metas.get_mut(self.ppn);
+        meta.ref_count -= 1;
+        let last = meta.ref_count == 0;
+        // 回收页帧时页帧分配器可能会分配或释放内存，先释放FRAME_METAS
+        drop(metas);
+        if last {
+            frame_dealloc(self.ppn);
+        }
     }
 }
 
@@ -115,6 +328,56 @@ pub fn frame_alloc() -> Option<FrameTracker> {
         .map(FrameTracker::new)
 }
 
+/// 分配一个页帧，空闲页帧不够时先从各个进程中换出一些页面再分配，仍然不够时返回None
+/// 缺页处理、内核访问用户内存和fork都通过它分配用户页面，内存不足时返回错误而不是panic
+pub fn frame_alloc_or_reclaim() -> Option<FrameTracker> {
+    if let Some(frame) = frame_alloc() {
+        return Some(frame);
+    }
+    reclaim_frames();
+    let frame = frame_alloc();
+    if frame.is_none() {
+        println!(
+            "[kernel] No enough memory for frame allocation, free: {:#x}",
+            free_frame_count()
+        );
+    }
+    frame
+}
+
+/// 分配pages个物理上连续的页帧
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let start = FRAME_ALLOCATOR.exclusive_access().alloc_contiguous(pages)?;
+    Some(
+        (start.0..start.0 + pages)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+/// 给slab分配一个页帧，不清零也不用FrameTracker管理
+/// 页帧分配器内部分配内存时可能会再次进入这里，这时页帧分配器已经被借用，直接返回None
+pub(super) fn try_frame_alloc_raw() -> Option<PhysPageNum> {
+    FRAME_ALLOCATOR.try_exclusive_access()?.alloc()
+}
+
+/// 给内核堆分配pages个连续的页帧，同样不清零也不用FrameTracker管理
+pub(super) fn try_frame_alloc_contiguous_raw(pages: usize) -> Option<PhysPageNum> {
+    FRAME_ALLOCATOR
+        .try_exclusive_access()?
+        .alloc_contiguous(pages)
+}
+
+/// 剩余可分配的页帧数
+pub fn free_frame_count() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().free_frames()
+}
+
+/// 可分配的页帧总数
+pub fn total_frame_count() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().total_frames()
+}
+
 /// deallocate a frame
 fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
@@ -143,3 +406,33 @@ pub fn frame_allocator_test() {
     drop(v);
     println!("frame_allocator_test passed!");
 }
+
+#[allow(unused)]
+pub fn frame_alloc_contiguous_test() {
+    let free = free_frame_count();
+    let frames = frame_alloc_contiguous(5).unwrap();
+    for i in 1..frames.len() {
+        assert_eq!(frames[i].ppn.0, frames[0].ppn.0 + i);
+    }
+    assert_eq!(free_frame_count(), free - 5);
+    drop(frames);
+    // 释放后页帧全部还回去
+    assert_eq!(free_frame_count(), free);
+    println!("frame_alloc_contiguous_test passed!");
+}
+
+#[allow(unused)]
+pub fn frame_ref_count_test() {
+    let free = free_frame_count();
+    let frame = frame_alloc().unwrap();
+    let shared = frame.clone();
+    assert_eq!(shared.ppn, frame.ppn);
+    assert_eq!(frame.ref_count(), 2);
+    drop(frame);
+    // 还有一个句柄，页帧不会被回收
+    assert_eq!(shared.ref_count(), 1);
+    assert_eq!(free_frame_count(), free - 1);
+    drop(shared);
+    assert_eq!(free_frame_count(), free);
+    println!("frame_ref_count_test passed!");
+}
@@ -1,11 +1,137 @@
-use crate::config::KERNEL_HEAP_SIZE;
+use super::frame_allocator::try_frame_alloc_contiguous_raw;
+use super::slab::{slab_alloc, slab_dealloc};
+use crate::config::{
+    KERNEL_HEAP_GROW_PAGES, KERNEL_HEAP_RESERVE_PAGES, KERNEL_HEAP_SIZE, MEMORY_END, MEMORY_START,
+    PAGE_SIZE,
+};
+use crate::sync::UPSafeCell;
 use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ops::Range;
+use lazy_static::lazy_static;
+
+/// 小对象交给slab，大对象以及slab分配不了的时候交给内核堆
+struct KernelAllocator {
+    heap: LockedHeap,
+}
+
+impl KernelAllocator {
+    /// 扩充内核堆，新加入的内存至少要能放下layout
+    /// 先从页帧分配器拿一大块，拿不到就只拿刚好够用的，再不行就用预留的页帧
+    fn grow(&self, layout: Layout) -> bool {
+        // 页帧不一定按内核堆需要的大小对齐，多拿一倍才能保证切得出一块对齐的
+        let needed =
+            (layout.size().max(layout.align()).next_power_of_two() * 2).div_ceil(PAGE_SIZE);
+        // 分配页帧时页帧分配器可能会再次分配或释放内存，这时不能持有内核堆的锁
+        for pages in [needed.max(KERNEL_HEAP_GROW_PAGES), needed] {
+            if let Some(ppn) = try_frame_alloc_contiguous_raw(pages) {
+                let start = ppn.0 * PAGE_SIZE;
+                self.add_region(start..start + pages * PAGE_SIZE);
+                return true;
+            }
+        }
+        // 页帧分配器分配不出来，或者正被占用（比如它自己在分配内存）时用预留的页帧
+        let mut reserve = match HEAP_RESERVE.try_exclusive_access() {
+            Some(reserve) => reserve,
+            None => return false,
+        };
+        match reserve.take() {
+            Some(region) if region.len() >= needed * PAGE_SIZE => {
+                drop(reserve);
+                self.add_region(region);
+                true
+            }
+            region => {
+                *reserve = region;
+                false
+            }
+        }
+    }
+
+    /// 把一段从页帧分配器拿到的内存加入内核堆
+    fn add_region(&self, region: Range<usize>) {
+        let mut pages = HEAP_PAGES.exclusive_access();
+        for addr in region.clone().step_by(PAGE_SIZE) {
+            let index = (addr - MEMORY_START) / PAGE_SIZE;
+            pages[index / 64] |= 1 << (index % 64);
+        }
+        drop(pages);
+        unsafe { self.heap.lock().add_to_heap(region.start, region.end) };
+    }
+}
+
+/// 预留的页帧用掉之后，在页帧分配器空闲时重新预留一块
+fn refill_reserve() {
+    let mut reserve = match HEAP_RESERVE.try_exclusive_access() {
+        Some(reserve) if reserve.is_none() => reserve,
+        _ => return,
+    };
+    if let Some(ppn) = try_frame_alloc_contiguous_raw(KERNEL_HEAP_RESERVE_PAGES) {
+        let start = ppn.0 * PAGE_SIZE;
+        *reserve = Some(start..start + KERNEL_HEAP_RESERVE_PAGES * PAGE_SIZE);
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Some(ptr) = slab_alloc(layout) {
+            return ptr;
+        }
+        loop {
+            // 先把结果取出来，保证扩充内核堆时已经释放了锁
+            let result = self.heap.lock().alloc(layout);
+            match result {
+                Ok(ptr) => {
+                    refill_reserve();
+                    return ptr.as_ptr();
+                }
+                // 内核堆用完了就扩充之后再试
+                Err(_) if self.grow(layout) => continue,
+                Err(_) => return core::ptr::null_mut(),
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // 内核堆之外的对象都是slab分配的
+        if in_heap(ptr as usize) {
+            self.heap.dealloc(ptr, layout);
+        } else {
+            slab_dealloc(ptr, layout);
+        }
+    }
+}
 
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelAllocator = KernelAllocator {
+    heap: LockedHeap::empty(),
+};
 
 static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
+/// 物理内存中每一页一位，记录哪些页帧被加入了内核堆，扩充的次数因此没有上限
+/// 不能用Vec之类的数据结构，修改它的时候不能再分配内存
+const HEAP_PAGES_WORDS: usize = (MEMORY_END - MEMORY_START) / PAGE_SIZE / 64;
+
+lazy_static! {
+    static ref HEAP_PAGES: UPSafeCell<[u64; HEAP_PAGES_WORDS]> =
+        UPSafeCell::new([0; HEAP_PAGES_WORDS]);
+    /// 给内核堆预留的页帧，页帧分配器分配不出来或者正被占用时（比如它自己在分配内存）使用
+    static ref HEAP_RESERVE: UPSafeCell<Option<Range<usize>>> = UPSafeCell::new(None);
+}
+
+fn in_heap(addr: usize) -> bool {
+    let start = unsafe { HEAP_SPACE.as_ptr() as usize };
+    if (start..start + KERNEL_HEAP_SIZE).contains(&addr) {
+        return true;
+    }
+    if !(MEMORY_START..MEMORY_END).contains(&addr) {
+        return false;
+    }
+    let index = (addr - MEMORY_START) / PAGE_SIZE;
+    HEAP_PAGES.exclusive_access()[index / 64] & (1 << (index % 64)) != 0
+}
+
 #[alloc_error_handler]
 pub fn handle_alloc_error(layot: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout : {:?}", layot)
@@ -14,11 +140,37 @@ pub fn handle_alloc_error(layot: core::alloc::Layout) -> ! {
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .heap
             .lock()
             .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
+/// 页帧分配器初始化之后给内核堆预留页帧
+pub fn init_heap_reserve() {
+    refill_reserve();
+}
+
+/// 内核堆的总大小和已经分配出去的字节数
+pub fn heap_stats() -> (usize, usize) {
+    let heap = HEAP_ALLOCATOR.heap.lock();
+    (heap.stats_total_bytes(), heap.stats_alloc_actual())
+}
+
+#[allow(unused)]
+pub fn heap_grow_test() {
+    use crate::println;
+    use alloc::vec::Vec;
+    let (total, _) = heap_stats();
+    // 超过内核堆初始大小的分配会让内核堆扩充
+    let mut v: Vec<u8> = Vec::with_capacity(KERNEL_HEAP_SIZE * 2);
+    v.resize(KERNEL_HEAP_SIZE * 2, 0x5a);
+    assert!(v.iter().all(|&x| x == 0x5a));
+    assert!(heap_stats().0 > total);
+    drop(v);
+    println!("heap_grow_test passed!");
+}
+
 #[allow(unused)]
 pub fn heap_test() {
     use crate::println;
@@ -29,8 +181,9 @@ pub fn heap_test() {
         fn ebss();
     }
     let bss_range = sbss as usize..ebss as usize;
-    let a = Box::new(5);
-    assert_eq!(*a, 5);
+    // 小对象由slab分配，要测试内核堆需要超过slab最大的对象
+    let a = Box::new([5usize; 512]);
+    assert_eq!(a[0], 5);
     assert!(bss_range.contains(&(a.as_ref() as *const _ as usize)));
     drop(a);
     let mut v: Vec<usize> = Vec::new();
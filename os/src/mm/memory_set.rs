@@ -1,7 +1,11 @@
 use core::{arch::asm, cmp::min};
 
 use crate::{
-    config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE_ADDRESS, TRAP_CONTEXT_ADDRESS, USER_STACK_SIZE},
+    config::{
+        ASLR_HEAP_PAGES, ASLR_PIE_PAGES, ASLR_STACK_PAGES, MEMORY_END, MMIO, PAGE_SIZE, PIE_BASE,
+        STACK_GUARD_GAP, TRAMPOLINE_ADDRESS, TRAP_CONTEXT_ADDRESS, USER_HEAP_LIMIT, USER_MMAP_BASE,
+        USER_MMAP_END, USER_STACK_SIZE,
+    },
     lang_items::StepByOne,
     println,
     sync::UPSafeCell,
@@ -9,8 +13,13 @@ use crate::{
 
 use super::{
     address::{PhysAddr, PhysPageNum, VPNRange, VirtAddr, VirtPageNum},
-    frame_allocator::{frame_alloc, FrameTracker},
-    page_table::{self, PTEFlags, PageTable, PageTableEntry},
+    asid::{asid_alloc, AsidHandle},
+    aslr::aslr_offset,
+    elf::{self, ElfError, ElfInfo},
+    frame_allocator::{frame_alloc_or_reclaim, FrameTracker},
+    page_table::{self, level_pages, PTEFlags, PageTable, PageTableEntry},
+    swap::SwapSlot,
+    zero_page::{is_zero_frame, zero_frame},
 };
 use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
 use bitflags::bitflags;
@@ -51,9 +60,21 @@ pub struct MapArea {
     vpn_range: VPNRange,
     // 用于保存每个虚拟页面与对应的物理页帧的键值对
     // 只有Framed类型的MapArea才会用到这个字段
+    // 写时复制时父子进程会共享同一个物理页帧，FrameTracker带有引用计数
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
     map_type: MapType,
     map_perm: MapPermission,
+    // 懒分配：映射时不分配物理页帧，第一次读时只读地映射共享的全0页帧，
+    // 第一次写时再分配一个全0的页帧
+    // 只对Framed类型的用户MapArea有意义
+    lazy: bool,
+    // 被换出到交换区的页面，它们没有页表项，缺页时再换入
+    swapped: BTreeMap<VirtPageNum, SwapSlot>,
+    // 共享内存段的映射，物理页帧和共享内存段以及其他进程的映射共享，
+    // fork时不写时复制，也不会被换出
+    shared: bool,
+    // 用户栈向下增长能到达的最低虚拟页号，不是用户栈的MapArea为None
+    stack_limit: Option<VirtPageNum>,
 }
 
 impl MapArea {
@@ -70,20 +91,56 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            lazy: false,
+            swapped: BTreeMap::new(),
+            shared: false,
+            stack_limit: None,
         }
     }
 
-    pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+    /// 创建一个懒分配的Framed类型MapArea
+    pub fn new_lazy(start_va: VirtAddr, end_va: VirtAddr, map_perm: MapPermission) -> Self {
+        let mut map_area = Self::new(start_va, end_va, MapType::Framed, map_perm);
+        map_area.lazy = true;
+        map_area
+    }
+
+    /// 映射整个MapArea，内存不足时撤销已经建立的映射并返回false
+    pub fn map(&mut self, page_table: &mut PageTable) -> bool {
+        // 懒分配的页面等到缺页时再映射
+        if self.lazy {
+            return true;
         }
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        let mut vpn = start;
+        while vpn < end {
+            if self.is_huge_at(vpn) {
+                let flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+                page_table.map_huge(vpn, PhysPageNum(vpn.0), flags, 1);
+                vpn.0 += level_pages(1);
+            } else if self.map_one(page_table, vpn) {
+                vpn.step();
+            } else {
+                // 只有Framed类型会分配失败，它不会用大页映射
+                for mapped in VPNRange::new(start, vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return false;
+            }
+        }
+        true
     }
 
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    /// 映射vpn，Framed类型要分配一个物理页帧，内存不足时返回false
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         let ppn: PhysPageNum = match self.map_type {
             MapType::Identical => PhysPageNum(vpn.0),
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = match frame_alloc_or_reclaim() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
                 let ppn = frame.ppn;
                 self.data_frames.insert(vpn, frame);
                 ppn
@@ -92,13 +149,31 @@ impl MapArea {
 
         let flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, flags);
+        true
+    }
+
+    /// 恒等映射中按2MiB对齐的整块直接用大页映射，节省页表和TLB
+    /// map和unmap都按这个规则划分，保证以同样的粒度建立和删除页表项
+    fn is_huge_at(&self, vpn: VirtPageNum) -> bool {
+        let huge_pages = level_pages(1);
+        self.map_type == MapType::Identical
+            && vpn.0 % huge_pages == 0
+            && vpn.0 + huge_pages <= self.vpn_range.get_end().0
     }
 
     #[allow(unused)]
-    /// unmap所有
+    /// unmap所有，大页只需要删除一个页表项
     pub fn unmap(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.unmap_one(page_table, vpn);
+        let end = self.vpn_range.get_end();
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < end {
+            if self.is_huge_at(vpn) {
+                page_table.unmap(vpn);
+                vpn.0 += level_pages(1);
+            } else {
+                self.unmap_one(page_table, vpn);
+                vpn.step();
+            }
         }
     }
 
@@ -115,13 +190,14 @@ impl MapArea {
 
         // 为什么不是上面的逻辑？
         // 哦，下面只是上面的简化版，没事了
-        if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+        if self.map_type == MapType::Framed && self.data_frames.remove(&vpn).is_none() {
+            // 懒分配的页面可能从来没有被映射过，换出的页面只需要回收交换槽
+            self.swapped.remove(&vpn);
+            return;
         }
         page_table.unmap(vpn);
     }
 
-    #[allow(unused)]
     pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
         for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
             self.unmap_one(page_table, vpn);
@@ -129,12 +205,21 @@ impl MapArea {
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
 
-    #[allow(unused)]
-    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn);
+    /// 扩大到new_end，内存不足时撤销新建立的映射并返回false，大小保持不变
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> bool {
+        if !self.lazy {
+            let old_end = self.vpn_range.get_end();
+            for vpn in VPNRange::new(old_end, new_end) {
+                if !self.map_one(page_table, vpn) {
+                    for mapped in VPNRange::new(old_end, vpn) {
+                        self.unmap_one(page_table, mapped);
+                    }
+                    return false;
+                }
+            }
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        true
     }
 
     /// data: start-aligned but maybe with shorter length
@@ -160,6 +245,169 @@ impl MapArea {
         }
     }
 
+    /// 在at处把MapArea一分为二，自己保留[start, at)，返回[at, end)
+    /// 已经映射的物理页帧会跟着虚拟页面走，页表不需要改变
+    pub fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let right = Self {
+            vpn_range: VPNRange::new(at, self.vpn_range.get_end()),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            lazy: self.lazy,
+            swapped: self.swapped.split_off(&at),
+            shared: self.shared,
+            // 只有包含栈底的那一半还能向下增长
+            stack_limit: None,
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), at);
+        right
+    }
+
+    /// 修改整个MapArea的权限，同时更新页表项
+    pub fn set_perm(&mut self, page_table: &mut PageTable, map_perm: MapPermission) {
+        self.map_perm = map_perm;
+        let flags = PTEFlags::from_bits(map_perm.bits).unwrap();
+        match self.map_type {
+            MapType::Identical => {
+                let end = self.vpn_range.get_end();
+                let mut vpn = self.vpn_range.get_start();
+                while vpn < end {
+                    page_table.set_flags(vpn, flags);
+                    vpn.0 += if self.is_huge_at(vpn) {
+                        level_pages(1)
+                    } else {
+                        1
+                    };
+                }
+            }
+            // 懒分配的MapArea中只有已经映射的页面才有页表项
+            // 写时复制共享的页帧和全0页帧仍然不能写，写的时候再复制
+            MapType::Framed => {
+                for (vpn, frame) in self.data_frames.iter() {
+                    if !self.shared && frame.ref_count() > 1 {
+                        page_table.set_flags(*vpn, flags - PTEFlags::W);
+                    } else {
+                        page_table.set_flags(*vpn, flags);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 处理用户程序对vpn的缺页异常，access为这次访问需要的权限(R/W/X)
+    /// 懒分配的页面在第一次读时映射共享的全0页帧，第一次写时映射一个新的全0页帧，
+    /// 写时复制的页面在写的时候复制一份，换出的页面从交换区读回来
+    /// 返回是否处理成功，失败说明是真正的非法访问，或者内存不足
+    pub fn handle_page_fault(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        access: MapPermission,
+    ) -> bool {
+        if self.map_type != MapType::Framed || !self.map_perm.contains(access | MapPermission::U) {
+            return false;
+        }
+        match page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => {
+                if access == MapPermission::W && !pte.writable() {
+                    return self.handle_cow(page_table, vpn);
+                }
+                // 硬件不自动设置A/D位时（Svade）访问会触发缺页异常，由内核来设置
+                if !pte.accessed() || (access == MapPermission::W && !pte.dirty()) {
+                    let mut flags = pte.flags() | PTEFlags::A;
+                    if access == MapPermission::W {
+                        flags |= PTEFlags::D;
+                    }
+                    page_table.set_flags(vpn, flags);
+                    return true;
+                }
+                false
+            }
+            _ if self.swapped.contains_key(&vpn) => self.swap_in(page_table, vpn),
+            _ if self.lazy && access == MapPermission::W => self.map_one(page_table, vpn),
+            _ if self.lazy => {
+                self.map_zero_page(page_table, vpn);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 把vpn只读地映射到共享的全0页帧
+    fn map_zero_page(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let frame = zero_frame();
+        let flags = PTEFlags::from_bits(self.map_perm.bits).unwrap() - PTEFlags::W;
+        page_table.map(vpn, frame.ppn, flags);
+        self.data_frames.insert(vpn, frame);
+    }
+
+    /// 处理对vpn的写缺页异常，如果这一页是写时复制的共享页，
+    /// 就复制一份（或者在只剩自己使用时直接恢复写权限），返回是否处理成功，内存不足时也返回false
+    fn handle_cow(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        // 只有本来可写、但页表项被去掉了写权限的页面才是写时复制的页面
+        if !self.map_perm.contains(MapPermission::W)
+            || page_table.translate(vpn).unwrap().writable()
+        {
+            return false;
+        }
+        let (ppn, ref_count) = match self.data_frames.get(&vpn) {
+            Some(frame) => (frame.ppn, frame.ref_count()),
+            None => return false,
+        };
+        let flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if ref_count == 1 {
+            page_table.set_flags(vpn, flags);
+        } else {
+            // 新分配的页帧已经清零，全0页帧不用复制
+            let new_frame = match frame_alloc_or_reclaim() {
+                Some(frame) => frame,
+                None => return false,
+            };
+            if !is_zero_frame(ppn) {
+                new_frame
+                    .ppn
+                    .get_bytes_array()
+                    .copy_from_slice(ppn.get_bytes_array());
+            }
+            page_table.unmap(vpn);
+            page_table.map(vpn, new_frame.ppn, flags);
+            self.data_frames.insert(vpn, new_frame);
+        }
+        true
+    }
+
+    /// 用户的Framed页面才能换出，TrapContext等内核直接访问的页面和共享内存不能换出
+    fn swappable(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U) && !self.shared
+    }
+
+    /// 把vpn对应的页面写到交换区并回收它的物理页帧，交换区满了返回false
+    fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let slot = match SwapSlot::swap_out(self.data_frames[&vpn].ppn) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        page_table.unmap(vpn);
+        // 物理页帧在这里被回收
+        self.data_frames.remove(&vpn);
+        self.swapped.insert(vpn, slot);
+        true
+    }
+
+    /// 把换出的页面vpn读回一个新的物理页帧中，交换槽随之回收，内存不足时返回false
+    fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let frame = match frame_alloc_or_reclaim() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let slot = self.swapped.remove(&vpn).unwrap();
+        slot.read(frame.ppn);
+        let flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.map(vpn, frame.ppn, flags);
+        self.data_frames.insert(vpn, frame);
+        true
+    }
+
     /// 从另一个MapArea构造新的MapArea，注意这个不会复制数据
     pub fn from_another(another: &MapArea) -> Self {
         Self {
@@ -167,24 +415,51 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            lazy: another.lazy,
+            swapped: BTreeMap::new(),
+            shared: another.shared,
+            stack_limit: another.stack_limit,
         }
     }
+
+    fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+
+    /// vpn是否在用户栈下方、还能通过向下增长覆盖到的范围内
+    fn can_grow_to(&self, vpn: VirtPageNum) -> bool {
+        matches!(self.stack_limit, Some(limit) if limit <= vpn && vpn < self.vpn_range.get_start())
+    }
 }
 
 // 每个进程持有一个
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    // clock算法的指针，下一次从这个虚拟页号开始寻找要换出的页面
+    clock_hand: VirtPageNum,
+    asid: AsidHandle,
 }
 
 impl MemorySet {
     pub fn new_bare() -> Self {
+        let asid = asid_alloc();
+        let mut page_table = PageTable::new();
+        page_table.set_asid(asid.0);
         Self {
-            page_table: PageTable::new(),
+            page_table,
             areas: Vec::new(),
+            clock_hand: VirtPageNum(0),
+            asid,
         }
     }
 
+    /// 重新分配一个ASID，之后要重新activate
+    pub fn renew_asid(&mut self) {
+        self.asid = asid_alloc();
+        self.page_table.set_asid(self.asid.0);
+    }
+
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
@@ -199,17 +474,54 @@ impl MemorySet {
         }
     }
 
-    pub fn from_existed_user(user_space: &MemorySet) -> Self {
+    /// 复制用户地址空间，内存不足时返回None
+    pub fn from_existed_user(user_space: &mut MemorySet) -> Option<Self> {
         let mut new_memory_set = Self::new_bare();
         new_memory_set.map_trampoline();
 
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
             // 这里的new_area还没有实际映射到物理页帧
-            let new_area = MapArea::from_another(&area);
+            let mut new_area = MapArea::from_another(&area);
+
+            // 共享内存直接映射到同样的物理页帧
+            if area.shared {
+                let flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+                for (vpn, frame) in area.data_frames.iter() {
+                    new_memory_set.page_table.map(*vpn, frame.ppn, flags);
+                    new_area.data_frames.insert(*vpn, frame.clone());
+                }
+                new_memory_set.areas.push(new_area);
+                continue;
+            }
+
+            // 用户能访问的页面采用写时复制：父子进程共享物理页帧，并且都去掉写权限，
+            // 之后谁先写就在trap_handler中给谁复制一份
+            // TrapContext没有U权限，内核会直接写它，所以还是要立即复制
+            if area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::U) {
+                let mut flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+                flags.remove(PTEFlags::W);
+                for (vpn, frame) in area.data_frames.iter() {
+                    user_space.page_table.set_flags(*vpn, flags);
+                    new_memory_set.page_table.map(*vpn, frame.ppn, flags);
+                    new_area.data_frames.insert(*vpn, frame.clone());
+                }
+                // 换出的页面不共享交换槽，直接给子进程读一份到新的物理页帧中
+                for (vpn, slot) in area.swapped.iter() {
+                    let frame = frame_alloc_or_reclaim()?;
+                    slot.read(frame.ppn);
+                    let flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+                    new_memory_set.page_table.map(*vpn, frame.ppn, flags);
+                    new_area.data_frames.insert(*vpn, frame);
+                }
+                new_memory_set.areas.push(new_area);
+                continue;
+            }
 
             // push的时候会进行映射
-            new_memory_set.push(new_area, None);
+            if !new_memory_set.try_push(new_area, None) {
+                return None;
+            }
 
             // 因为两个area的vpn_range是相同的，
             // 所以在虚拟地址空间上看，两者是一样的
@@ -225,7 +537,7 @@ impl MemorySet {
             }
         }
 
-        new_memory_set
+        Some(new_memory_set)
     }
 
     /// 释放用户空间的内存
@@ -299,18 +611,39 @@ impl MemorySet {
             ),
             None,
         );
+        println!("mapping memory-mapped registers");
+        for &(start, len) in MMIO {
+            memory_set.push(
+                MapArea::new(
+                    start.into(),
+                    (start + len).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
         println!("kernel memory space setup success!");
         memory_set
     }
 
     // 在地址空间中插入一个新的逻辑段map_area，data为可选的初始化数据
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    // 只用于内核自己的映射，内存不足时直接panic
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) {
+        assert!(self.try_push(map_area, data), "out of memory");
+    }
+
+    /// 和push一样，但内存不足时返回false，map_area不会被插入
+    fn try_push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> bool {
+        if !map_area.map(&mut self.page_table) {
+            return false;
+        }
         // println!("MapType: {:?}", map_area.map_type);
         if let Some(data) = data {
             map_area.copy_data(&self.page_table, data);
         }
         self.areas.push(map_area);
+        true
     }
 
     pub fn insert_framed_area(
@@ -325,6 +658,30 @@ impl MemorySet {
         );
     }
 
+    /// 插入一个懒分配的Framed类型MapArea，物理页帧在第一次访问时才分配
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        // 懒分配的MapArea不会分配物理页帧
+        self.push(MapArea::new_lazy(start_va, end_va, permission), None);
+    }
+
+    /// 插入一个懒分配的用户栈，初始为[start_va, end_va)，缺页时最多向下增长到limit_va
+    pub fn insert_stack_area(
+        &mut self,
+        limit_va: VirtAddr,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        let mut map_area = MapArea::new_lazy(start_va, end_va, permission);
+        map_area.stack_limit = Some(limit_va.floor());
+        self.push(map_area, None);
+    }
+
     fn map_trampoline(&mut self) {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE_ADDRESS).into(),
@@ -333,49 +690,43 @@ impl MemorySet {
         );
     }
 
-    #[allow(unused)]
+    /// 将起始地址为start的MapArea缩小到new_end
+    // new_end所在的页可能还有一部分在用，所以这里要上取整
     pub fn shrink_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
         if let Some(area) = self
             .areas
             .iter_mut()
             .find(|area| area.vpn_range.get_start() == start.floor())
         {
-            area.shrink_to(&mut self.page_table, new_end.floor());
+            area.shrink_to(&mut self.page_table, new_end.ceil());
             true
         } else {
             false
         }
     }
 
-    #[allow(unused)]
+    /// 将起始地址为start的MapArea扩大到new_end，找不到MapArea或者内存不足时返回false
     pub fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
         if let Some(area) = self
             .areas
             .iter_mut()
             .find(|area| area.vpn_range.get_start() == start.floor())
         {
-            true
+            area.append_to(&mut self.page_table, new_end.ceil())
         } else {
             false
         }
     }
 
     /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
-    /// 返回(memory_set, user_sp, entry_point)
+    /// also returns user_sp, heap bottom and entry point.
+    /// 返回memory_set和用户栈、堆的基地址、入口地址等信息，elf不合法时返回ElfError
     // 从elf文件中加载用户程序，创建其地址空间
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
-        // map trampoline
-        memory_set.map_trampoline();
-        // 用xmas-elf库解析elf文件
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, ElfInfo), ElfError> {
+        // 用xmas-elf库解析elf文件，它会检查magic number和文件头的大小
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(|_| ElfError::Malformed)?;
         let elf_header = elf.header;
 
-        // 根据magic number判断elf文件是否合法
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-
         // ph: program header
         // ​存放的是系统加载可执行程序所需要的所有信息，是程序装载必须的一部分。
         // Program header 是由一个或多个相同结构的程序段(Segment)组成的。
@@ -385,13 +736,28 @@ impl MemorySet {
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
 
+        // 位置无关的可执行文件（ET_DYN）的地址从0开始，整体加载到PIE_BASE之上，
+        // 普通的可执行文件按链接时的地址加载
+        let load_bias = match elf_header.pt2.type_().as_type() {
+            xmas_elf::header::Type::SharedObject => PIE_BASE + aslr_offset(ASLR_PIE_PAGES),
+            _ => 0,
+        };
+        // 先检查完整个文件再开始映射
+        elf::validate(&elf, load_bias)?;
+        let relocations = elf::relocations(&elf)?;
+
+        let mut memory_set = Self::new_bare();
+        // map trampoline
+        memory_set.map_trampoline();
+
         // 为每个程序段创建一个MapArea
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
                 // 获取地址范围
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+                let start_va: VirtAddr = (load_bias + ph.virtual_addr() as usize).into();
+                let end_va: VirtAddr =
+                    (load_bias + (ph.virtual_addr() + ph.mem_size()) as usize).into();
                 // 设置权限标志位
                 let mut map_perm = MapPermission::U;
                 let ph_flags = ph.flags();
@@ -406,37 +772,390 @@ impl MemorySet {
                 }
                 // 创建MapArea
                 // println!("App {}， start_va: {:#x}, end_va: {:#x}", i, start_va.0, end_va.0);
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                // 文件中有内容的页面立即分配并拷贝数据，
+                // 之后只有0的页面（.bss）懒分配
+                let file_end_va: VirtAddr =
+                    (load_bias + (ph.virtual_addr() + ph.file_size()) as usize).into();
+                let lazy_start_vpn = file_end_va.ceil();
+                if start_va.floor() < lazy_start_vpn {
+                    let map_area = MapArea::new(
+                        start_va,
+                        lazy_start_vpn.min(end_va.ceil()).into(),
+                        MapType::Framed,
+                        map_perm,
+                    );
+                    let data = elf::relocate(
+                        &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
+                        ph.virtual_addr() as usize,
+                        &relocations,
+                        load_bias,
+                    );
+                    if !memory_set.try_push(map_area, Some(&data)) {
+                        return Err(ElfError::NoMemory);
+                    }
+                }
+                if lazy_start_vpn < end_va.ceil() {
+                    memory_set.insert_lazy_area(
+                        lazy_start_vpn.max(start_va.floor()).into(),
+                        end_va,
+                        map_perm,
+                    );
+                }
+                max_end_vpn = max_end_vpn.max(end_va.ceil());
             }
         }
 
-        // 计算用户栈的起始地址
-        // map user stack with U flags
+        // 计算用户堆的起始地址
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
+        let mut heap_bottom: usize = max_end_va.into();
+
+        // 堆的前一页留给guard page，开启ASLR时再随机后移若干页
+        heap_bottom += PAGE_SIZE + aslr_offset(ASLR_HEAP_PAGES);
 
-        // 栈的前一页留给guard page
-        // 所以内存分布为[.text, .rodata, .data, .bss, guard page, user stack, (very big space), trap context, trampoline]
-        // 在这个版本中，似乎每个程序是没有堆的，所有程序共用内核.bss段的空间作为堆
-        user_stack_bottom += PAGE_SIZE;
+        // 堆一开始是空的，之后通过sbrk调用append_to/shrink_to改变它的大小
+        // 堆是懒分配的，只有真正用到的页面才会分配物理页帧
+        memory_set.insert_lazy_area(
+            heap_bottom.into(),
+            heap_bottom.into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+
+        // 计算用户栈的起始地址
+        // 堆最多增长USER_HEAP_LIMIT，之后再留出STACK_GUARD_GAP大小的保护区间
+        // 所以内存分布为[.text, .rodata, .data, .bss, guard page, heap, guard gap, user stack, (very big space), trap context, trampoline]
+        // 开启ASLR时用户栈同样随机后移若干页
+        let user_stack_bottom =
+            heap_bottom + USER_HEAP_LIMIT + STACK_GUARD_GAP + aslr_offset(ASLR_STACK_PAGES);
 
-        // 返回MemorySet，用户栈基地址，程序入口地址
-        (
-            memory_set,
-            user_stack_bottom,
-            elf.header.pt2.entry_point() as usize,
-        )
+        let info = ElfInfo {
+            ustack_base: user_stack_bottom,
+            heap_bottom,
+            entry_point: load_bias + elf.header.pt2.entry_point() as usize,
+            stack_perm: elf::stack_permission(&elf),
+            tls: elf::tls_template(&elf, &relocations, load_bias),
+            phdr: elf::phdr_address(&elf, load_bias),
+            phnum: ph_count as usize,
+        };
+        Ok((memory_set, info))
     }
 
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
 
+    /// 处理用户程序对va的缺页异常（懒分配、写时复制、换入），access为这次访问需要的权限
+    /// 返回是否处理成功，失败说明是真正的非法访问
+    pub fn handle_page_fault(&mut self, va: VirtAddr, access: MapPermission) -> bool {
+        let vpn = va.floor();
+        if let Some(area) = self.areas.iter_mut().find(|area| area.contains(vpn)) {
+            area.handle_page_fault(&mut self.page_table, vpn, access)
+        } else if let Some(area) = self.areas.iter_mut().find(|area| area.can_grow_to(vpn)) {
+            // 先检查权限，非法的访问不能让栈增长
+            if !area.map_perm.contains(access | MapPermission::U) {
+                return false;
+            }
+            // 访问到用户栈下方时栈向下增长，中间的页面同样是懒分配的
+            area.vpn_range = VPNRange::new(vpn, area.vpn_range.get_end());
+            area.handle_page_fault(&mut self.page_table, vpn, access)
+        } else {
+            false
+        }
+    }
+
+    /// va是否落在某个用户栈增长上限下方、没有被映射的保护区间中，即用户栈溢出了
+    pub fn is_stack_overflow(&self, va: VirtAddr) -> bool {
+        let vpn = va.floor();
+        let gap_pages = STACK_GUARD_GAP / PAGE_SIZE;
+        !self.areas.iter().any(|area| area.contains(vpn))
+            && self
+                .areas
+                .iter()
+                .filter_map(|area| area.stack_limit)
+                .any(|limit| vpn < limit && vpn.0 + gap_pages >= limit.0)
+    }
+
+    /// 用户程序能否以access的权限访问vpn，以vpn所在MapArea的权限为准
+    /// 写时复制的页面在页表项中没有W位，换出的页面没有页表项，只看页表项是不够的
+    pub fn user_accessible(&self, vpn: VirtPageNum, access: MapPermission) -> bool {
+        self.areas
+            .iter()
+            .find(|area| area.contains(vpn) || area.can_grow_to(vpn))
+            .map_or(false, |area| {
+                area.map_perm.contains(access | MapPermission::U)
+            })
+    }
+
+    /// 驻留在物理内存中的用户页面数（RSS），共享的页帧在每个映射它的进程中都会被计入
+    /// 只读映射到全0页帧的懒分配页面没有自己的页帧，不计入
+    pub fn resident_pages(&self) -> usize {
+        self.areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| {
+                area.data_frames
+                    .values()
+                    .filter(|frame| !is_zero_frame(frame.ppn))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// 被换出到交换区的用户页面数
+    pub fn swapped_pages(&self) -> usize {
+        self.areas.iter().map(|area| area.swapped.len()).sum()
+    }
+
+    /// 用clock（second chance）算法从这个地址空间中换出最多count个用户页面，返回换出的页面数
+    /// 最近被访问过（A位为1）的页面先清除A位，等指针转回来时还没有被访问过才换出
+    pub fn swap_out(&mut self, count: usize) -> usize {
+        // 按虚拟页号排好序的候选页面，写时复制共享的页帧不换出
+        let mut candidates: Vec<(VirtPageNum, usize)> = self
+            .areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.swappable())
+            .flat_map(|(idx, area)| {
+                area.data_frames
+                    .iter()
+                    .filter(|(_, frame)| frame.ref_count() == 1)
+                    .map(move |(vpn, _)| (*vpn, idx))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return 0;
+        }
+        candidates.sort();
+        let start = candidates
+            .iter()
+            .position(|(vpn, _)| *vpn >= self.clock_hand)
+            .unwrap_or(0);
+        let mut swapped = 0;
+        // 最多转两圈，第二圈时所有页面的A位都已经被清除了
+        for i in 0..candidates.len() * 2 {
+            if swapped == count {
+                break;
+            }
+            let (vpn, idx) = candidates[(start + i) % candidates.len()];
+            // 第二圈时可能遇到已经换出的页面
+            let pte = match self.page_table.translate(vpn) {
+                Some(pte) if pte.is_valid() => pte,
+                _ => continue,
+            };
+            if pte.accessed() {
+                self.page_table.set_flags(vpn, pte.flags() - PTEFlags::A);
+                continue;
+            }
+            // 交换区满了
+            if !self.areas[idx].swap_out(&mut self.page_table, vpn) {
+                break;
+            }
+            self.clock_hand = VirtPageNum(vpn.0 + 1);
+            swapped += 1;
+        }
+        swapped
+    }
+
+    /// 匿名映射一段内存，start为0时由内核选择地址
+    /// 成功返回映射的起始地址，失败（地址不合法或与已有的MapArea重叠）返回None
+    pub fn mmap(&mut self, start: usize, len: usize, perm: MapPermission) -> Option<usize> {
+        let (start_vpn, end_vpn) = self.new_mmap_range(start, Self::len_to_pages(len)?)?;
+        self.insert_lazy_area(start_vpn.into(), end_vpn.into(), perm);
+        Some(VirtAddr::from(start_vpn).into())
+    }
+
+    /// 把共享内存段的物理页帧映射到start处，start为0时由内核选择地址
+    /// 成功返回映射的起始地址，失败返回None
+    pub fn attach_shm(&mut self, start: usize, frames: Vec<FrameTracker>) -> Option<usize> {
+        let (start_vpn, end_vpn) = self.new_mmap_range(start, frames.len())?;
+        let perm = MapPermission::R | MapPermission::W | MapPermission::U;
+        let mut map_area = MapArea::new(start_vpn.into(), end_vpn.into(), MapType::Framed, perm);
+        map_area.shared = true;
+        let flags = PTEFlags::from_bits(perm.bits).unwrap();
+        for (vpn, frame) in map_area.vpn_range.into_iter().zip(frames) {
+            self.page_table.map(vpn, frame.ppn, flags);
+            map_area.data_frames.insert(vpn, frame);
+        }
+        self.areas.push(map_area);
+        Some(VirtAddr::from(start_vpn).into())
+    }
+
+    /// 取消起始地址为start的共享内存映射
+    pub fn detach_shm(&mut self, start: usize) -> bool {
+        let start_vpn = VirtPageNum(start / PAGE_SIZE);
+        if let Some(idx) = self
+            .areas
+            .iter()
+            .position(|area| area.shared && area.vpn_range.get_start() == start_vpn)
+        {
+            let mut area = self.areas.remove(idx);
+            area.unmap(&mut self.page_table);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 取消[start, start + len)的映射，这段区间必须完全被mmap映射过
+    pub fn munmap(&mut self, start: usize, len: usize) -> bool {
+        let start_vpn = VirtPageNum(start / PAGE_SIZE);
+        let end_vpn = match Self::len_to_pages(len) {
+            Some(len_pages) => VirtPageNum(start_vpn.0 + len_pages),
+            None => return false,
+        };
+        if !Self::in_mmap_region(start_vpn, end_vpn)
+            || !self.is_covered(start_vpn, end_vpn)
+            || self.splits_shared(start_vpn, end_vpn)
+        {
+            return false;
+        }
+        for mut area in self.split_areas(start_vpn, end_vpn) {
+            area.unmap(&mut self.page_table);
+        }
+        true
+    }
+
+    /// 修改[start, start + len)的权限，这段区间必须完全被mmap映射过
+    pub fn mprotect(&mut self, start: usize, len: usize, perm: MapPermission) -> bool {
+        let start_vpn = VirtPageNum(start / PAGE_SIZE);
+        let end_vpn = match Self::len_to_pages(len) {
+            Some(len_pages) => VirtPageNum(start_vpn.0 + len_pages),
+            None => return false,
+        };
+        if !Self::in_mmap_region(start_vpn, end_vpn)
+            || !self.is_covered(start_vpn, end_vpn)
+            || self.splits_shared(start_vpn, end_vpn)
+        {
+            return false;
+        }
+        for mut area in self.split_areas(start_vpn, end_vpn) {
+            area.set_perm(&mut self.page_table, perm);
+            self.areas.push(area);
+        }
+        true
+    }
+
+    /// 在mmap区域中为len_pages个页面确定虚拟地址区间，start为0时由内核选择地址
+    /// start不在mmap区域中或者与已有的MapArea重叠时返回None
+    fn new_mmap_range(&self, start: usize, len_pages: usize) -> Option<(VirtPageNum, VirtPageNum)> {
+        let start_vpn = if start == 0 {
+            self.find_free_area(len_pages)?
+        } else {
+            VirtPageNum(start / PAGE_SIZE)
+        };
+        let end_vpn = VirtPageNum(start_vpn.0 + len_pages);
+        if !Self::in_mmap_region(start_vpn, end_vpn)
+            || self.areas.iter().any(|area| {
+                area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+            })
+        {
+            return None;
+        }
+        Some((start_vpn, end_vpn))
+    }
+
+    /// 长度上取整到页数，长度过大时返回None
+    fn len_to_pages(len: usize) -> Option<usize> {
+        if len > USER_MMAP_END - USER_MMAP_BASE {
+            None
+        } else {
+            Some((len + PAGE_SIZE - 1) / PAGE_SIZE)
+        }
+    }
+
+    /// mmap只能使用[USER_MMAP_BASE, USER_MMAP_END)这段虚拟地址，
+    /// 避免和堆、用户栈以及以后创建的线程的用户栈冲突
+    fn in_mmap_region(start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        VirtAddr::from(USER_MMAP_BASE).floor() <= start_vpn
+            && start_vpn < end_vpn
+            && end_vpn <= VirtAddr::from(USER_MMAP_END).floor()
+    }
+
+    /// 在mmap区域中找到一段长度为len_pages的空闲虚拟地址（first fit）
+    fn find_free_area(&self, len_pages: usize) -> Option<VirtPageNum> {
+        let mut ranges: Vec<(VirtPageNum, VirtPageNum)> = self
+            .areas
+            .iter()
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            .collect();
+        ranges.sort();
+        let mut start_vpn = VirtAddr::from(USER_MMAP_BASE).floor();
+        for (l, r) in ranges {
+            if start_vpn.0 + len_pages <= l.0 {
+                break;
+            }
+            if r > start_vpn {
+                start_vpn = r;
+            }
+        }
+        if start_vpn.0 + len_pages <= VirtAddr::from(USER_MMAP_END).floor().0 {
+            Some(start_vpn)
+        } else {
+            None
+        }
+    }
+
+    /// [start_vpn, end_vpn)中的每一页是否都属于某个MapArea
+    fn is_covered(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let covered: usize = self
+            .areas
+            .iter()
+            .map(|area| {
+                let l = area.vpn_range.get_start().max(start_vpn);
+                let r = area.vpn_range.get_end().min(end_vpn);
+                r.0.saturating_sub(l.0)
+            })
+            .sum();
+        covered == end_vpn.0 - start_vpn.0
+    }
+
+    /// [start_vpn, end_vpn)是否只覆盖了某个共享内存映射的一部分
+    /// 共享内存映射只能整个取消或修改，切开后detach_shm就找不到它了
+    fn splits_shared(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| {
+            let l = area.vpn_range.get_start();
+            let r = area.vpn_range.get_end();
+            area.shared && l < end_vpn && start_vpn < r && (l < start_vpn || end_vpn < r)
+        })
+    }
+
+    /// 把和[start_vpn, end_vpn)部分重叠的MapArea在区间边界处切开，
+    /// 区间外的部分留在地址空间中，区间内的部分从地址空间中取出并返回
+    fn split_areas(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> Vec<MapArea> {
+        let mut inside = Vec::new();
+        let mut outside = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            let l = area.vpn_range.get_start();
+            let r = area.vpn_range.get_end();
+            if r <= start_vpn || end_vpn <= l {
+                outside.push(area);
+                continue;
+            }
+            if l < start_vpn {
+                let right = area.split_off(start_vpn);
+                outside.push(area);
+                area = right;
+            }
+            if end_vpn < r {
+                outside.push(area.split_off(end_vpn));
+            }
+            inside.push(area);
+        }
+        self.areas = outside;
+        inside
+    }
+
+    /// 移除结束于end_vpn的MapArea，用户栈向下增长后起始地址会变，只能用结束地址找到它
+    pub fn remove_area_with_end_vpn(&mut self, end_vpn: VirtPageNum) {
+        if let Some(idx) = self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_end() == end_vpn)
+        {
+            let mut area = self.areas.remove(idx);
+            area.unmap(&mut self.page_table);
+        }
+    }
+
     ///Remove `MapArea` that starts with `start_vpn`
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
@@ -1,20 +1,49 @@
 mod address;
+mod asid;
+mod aslr;
+mod elf;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
+mod slab;
+mod swap;
+mod user_ptr;
+mod zero_page;
 
-pub use address::{PhysPageNum, VirtAddr};
+pub use address::{PhysAddr, PhysPageNum, VirtAddr};
+pub use elf::{ElfError, TlsTemplate, AT_NULL, AT_RANDOM};
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_contiguous, free_frame_count, total_frame_count, FrameTracker,
+};
+pub use heap_allocator::heap_stats;
 pub use memory_set::MapPermission;
+pub use shm::{shm_frames, shm_get, shm_remove};
+pub use slab::slab_stats;
+pub use swap::swap_stats;
 pub use memory_set::MemorySet;
 pub use memory_set::KERNEL_SPACE;
 
 pub use memory_set::kernel_token;
-pub use page_table::{translate_buffer, translate_ref, translate_ref_mut, translate_str};
+pub use page_table::PageTable;
+pub use user_ptr::{read_user_str, UserPtr, UserSlice};
+pub use zero_page::zero_page_stats;
 
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
+    heap_allocator::init_heap_reserve();
+    // 页帧分配器可用之后小对象才能从slab分配
+    slab::init_slab();
     // 开启分页机制
     KERNEL_SPACE.exclusive_access().activate();
+    // 开启分页之后才能检测ASID的位数，再给内核地址空间分配一个ASID
+    asid::init_asid();
+    let mut kernel_space = KERNEL_SPACE.exclusive_access();
+    kernel_space.renew_asid();
+    kernel_space.activate();
+    drop(kernel_space);
+    swap::init_swap();
+    aslr::init_aslr();
 }
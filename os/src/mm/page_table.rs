@@ -1,18 +1,18 @@
+use core::arch::asm;
 use core::panic;
 
-use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 
 use bitflags::*;
 
-use crate::lang_items::StepByOne;
 use crate::println;
 
 use super::address::PhysAddr;
 use super::address::PhysPageNum;
 use super::address::VirtPageNum;
 use super::address::PPN_WIDTH_SV39;
+use super::asid::{asid_token, token_asid};
 use super::frame_allocator::frame_alloc;
 use super::frame_allocator::FrameTracker;
 use super::VirtAddr;
@@ -74,12 +74,39 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+
+    // R/W/X中有一位为1的有效页表项是叶子节点，否则指向下一级页表
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid()
+            && (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
+
+    // A位在页面被访问时由硬件置1，页面置换算法用它判断页面最近是否被访问过
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
+}
+
+/// 第level级（0为根页表）的叶子页表项映射的页面数，
+/// 第1级为2MiB的大页，第0级为1GiB的大页
+pub const fn level_pages(level: usize) -> usize {
+    1 << (9 * (2 - level))
 }
 
 pub struct PageTable {
     // 对应原版的root_ppn
     root_table_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
+    // 地址空间标识符，由持有这个页表的MemorySet分配
+    asid: usize,
 }
 
 impl PageTable {
@@ -88,17 +115,32 @@ impl PageTable {
         PageTable {
             root_table_ppn: frame.ppn,
             frames: vec![frame],
+            asid: 0,
         }
     }
 
     // 插入一个虚拟页号到物理页号的映射（即创建/修改一个页表项）
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+        self.map_huge(vpn, ppn, flags, 2);
+    }
+
+    /// 在第level级页表中插入一个叶子页表项，映射level_pages(level)个页面
+    /// level为2时就是普通的4KiB页面，vpn和ppn都要按大页的大小对齐
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags, level: usize) {
+        assert!(
+            vpn.0 % level_pages(level) == 0 && ppn.0 % level_pages(level) == 0,
+            "vpn {:?} or ppn {:?} is not aligned to level {}",
+            vpn,
+            ppn,
+            level
+        );
+        let pte = self.find_pte_create(vpn, level).unwrap();
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.flush(vpn);
     }
 
-    // 删除一个虚拟页号的映射
+    // 删除一个虚拟页号的映射，如果vpn在大页中，整个大页的映射都会被删除
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
         assert!(
@@ -107,14 +149,45 @@ impl PageTable {
             vpn
         );
         *pte = PageTableEntry::new_empty();
+        self.flush(vpn);
+    }
+
+    // 修改一个已经映射的虚拟页号的权限，物理页号不变，如果vpn在大页中，修改的是整个大页
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(
+            pte.is_valid(),
+            "vpn {:?} is not mapped before setting flags",
+            vpn
+        );
+        *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V);
+        self.flush(vpn);
+    }
+
+    pub fn set_asid(&mut self, asid: usize) {
+        self.asid = asid;
+    }
+
+    // 页表项修改后，只刷新TLB中这个地址空间的vpn对应的项
+    fn flush(&self, vpn: VirtPageNum) {
+        let va: usize = VirtAddr::from(vpn).into();
+        let asid = self.asid;
+        unsafe { asm!("sfence.vma {}, {}", in(reg) va, in(reg) asid) };
     }
 
+    /// 返回vpn对应的页表项，vpn在大页中时，返回的页表项的物理页号是vpn实际对应的物理页号
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_pte_level(vpn).map(|(pte, level)| {
+            // 大页的页表项中只有大页的起始物理页号，还要加上vpn在大页中的偏移
+            let offset = vpn.0 & (level_pages(level) - 1);
+            let mut pte = *pte;
+            pte.bits += offset << 10;
+            pte
+        })
     }
 
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.translate(va.clone().floor()).map(|pte| {
             //println!("translate_va:va = {:?}", va);
             let aligned_pa: PhysAddr = pte.ppn().into();
             //println!("translate_va:pa_align = {:?}", aligned_pa);
@@ -126,8 +199,9 @@ impl PageTable {
 
     pub fn token(&self) -> usize {
         // 左边 0b1000 << 60 将satp的MODE字段设置为8 表示启用Sv39模式
+        // 中间 ASID
         // 右边 将根页表所在物理页号写到satp中
-        8usize << 60 | self.root_table_ppn.0
+        8usize << 60 | asid_token(self.asid) | self.root_table_ppn.0
     }
 
     /// Temporarily used to get arguments from user space.
@@ -136,20 +210,22 @@ impl PageTable {
         Self {
             root_table_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
             frames: Vec::new(),
+            asid: token_asid(satp),
         }
     }
 
-    // 获取一个虚拟页号对应的物理页号，如果不存在会自动创建
-    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    // 获取一个虚拟页号在第level级页表中的页表项，中间的页表如果不存在会自动创建
+    fn find_pte_create(&mut self, vpn: VirtPageNum, level: usize) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_table_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == level {
                 result = Some(pte);
                 break;
             }
+            assert!(!pte.is_leaf(), "vpn {:?} is mapped by a huge page", vpn);
             if !pte.is_valid() {
                 let frame = frame_alloc().unwrap();
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
@@ -162,85 +238,25 @@ impl PageTable {
 
     // 获取一个虚拟页号对应的物理页号，如果不存在则返回None
     fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_level(vpn).map(|(pte, _)| pte)
+    }
+
+    // 获取一个虚拟页号对应的叶子页表项以及它所在的级别，遇到大页时提前返回
+    fn find_pte_level(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_table_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
-                break;
+            if i == 2 || pte.is_leaf() {
+                return Some((pte, i));
             }
             if !pte.is_valid() {
                 return None;
             }
             ppn = pte.ppn();
         }
-        result
-    }
-}
-
-/// 将一段缓冲区（连续的虚拟地址）翻译成若干个对应的物理页号
-/// 返回时自动转化为可直接访问的若干个[u8]
-pub fn translate_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            // 此时end_va是下一页的起始地址
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            // 此时end_va和start_va在同一页
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
-        }
-        start = end_va.into();
+        None
     }
-    v
-}
-
-/// 从内核空间外的某个用户空间获得一个字符串
-pub fn translate_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-
-    let mut str = String::new();
-    let mut va = ptr as usize;
-    loop {
-        // 因为我们的内核是Identical Mapping，所以翻译得到的物理地址就是内核地址空间的地址，可以直接访问
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        // println!("sasd");
-        if ch == 0 {
-            break;
-        }
-        str.push(ch as char);
-        va += 1;
-    }
-    str
-}
-
-/// 把用户地址空间的指针转成内核可操作的引用
-pub fn translate_ref_mut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    let page_table = PageTable::from_token(token);
-    page_table
-        .translate_va((ptr as usize).into())
-        .unwrap()
-        .get_mut()
-}
-
-pub fn translate_ref<T>(token: usize, ptr: *const T) -> &'static T {
-    let page_table = PageTable::from_token(token);
-    let pa = page_table.translate_va((ptr as usize).into()).unwrap();
-    unsafe { (pa.0 as *mut T).as_ref() }.unwrap()
 }
 
 #[allow(unused)]
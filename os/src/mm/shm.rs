@@ -0,0 +1,83 @@
+//! 共享内存段：同一组物理页帧可以被映射到多个进程的地址空间中
+
+use super::frame_allocator::{frame_alloc, total_frame_count, FrameTracker};
+use crate::config::PAGE_SIZE;
+use crate::sync::UPSafeCell;
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use lazy_static::lazy_static;
+
+// key为IPC_PRIVATE时总是创建一个新的共享内存段
+pub const IPC_PRIVATE: usize = 0;
+
+struct ShmSegment {
+    key: usize,
+    // 映射到地址空间中的MapArea也持有这些页帧，
+    // 所以共享内存段被删除后，页帧会一直活到最后一个映射被取消
+    frames: Vec<FrameTracker>,
+}
+
+struct ShmManager {
+    next_id: usize,
+    segments: BTreeMap<usize, ShmSegment>,
+}
+
+impl ShmManager {
+    fn new() -> Self {
+        Self {
+            next_id: 0,
+            segments: BTreeMap::new(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SHM_MANAGER: UPSafeCell<ShmManager> = UPSafeCell::new(ShmManager::new());
+}
+
+/// 获取key对应的共享内存段，不存在时创建一个len字节的新段，返回它的id
+/// 已经存在的段比len小，或者物理内存不够时返回None
+pub fn shm_get(key: usize, len: usize) -> Option<usize> {
+    if key != IPC_PRIVATE {
+        let manager = SHM_MANAGER.exclusive_access();
+        if let Some((id, segment)) = manager.segments.iter().find(|(_, seg)| seg.key == key) {
+            return if segment.frames.len() * PAGE_SIZE >= len {
+                Some(*id)
+            } else {
+                None
+            };
+        }
+    }
+    // 超过物理内存总量的请求不可能满足，不用一页一页地分配到失败为止
+    let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    if pages > total_frame_count() {
+        return None;
+    }
+    // 分配页帧时不持有SHM_MANAGER，页帧不够时中途失败，已经分配的页帧随frames回收
+    let mut frames = Vec::with_capacity(pages);
+    for _ in 0..pages {
+        frames.push(frame_alloc()?);
+    }
+    let mut manager = SHM_MANAGER.exclusive_access();
+    let id = manager.next_id;
+    manager.next_id += 1;
+    manager.segments.insert(id, ShmSegment { key, frames });
+    Some(id)
+}
+
+/// 共享内存段id的所有物理页帧
+pub fn shm_frames(id: usize) -> Option<Vec<FrameTracker>> {
+    SHM_MANAGER
+        .exclusive_access()
+        .segments
+        .get(&id)
+        .map(|segment| segment.frames.clone())
+}
+
+/// 删除共享内存段id，已经映射的地址空间不受影响
+pub fn shm_remove(id: usize) -> bool {
+    SHM_MANAGER
+        .exclusive_access()
+        .segments
+        .remove(&id)
+        .is_some()
+}
@@ -0,0 +1,154 @@
+//! 内核小对象的slab分配器
+//!
+//! 每个cache管理一种大小的对象，从页帧分配器拿整页切成等大的对象，
+//! 空闲对象用侵入式链表串起来。TaskControlBlock这类频繁创建销毁的对象
+//! 走这里，不会在内核堆里留下碎片
+
+use super::address::PhysPageNum;
+use super::frame_allocator::try_frame_alloc_raw;
+use crate::config::PAGE_SIZE;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+
+/// 各个cache的对象大小，对象按自身大小对齐
+const SLAB_SIZES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+struct SlabCache {
+    object_size: usize,
+    /// 空闲链表的表头，0表示链表为空，空闲对象的前8个字节存放下一个空闲对象的地址
+    free_list: usize,
+    pages: usize,
+    in_use: usize,
+    free: usize,
+}
+
+impl SlabCache {
+    fn new(object_size: usize) -> Self {
+        Self {
+            object_size,
+            free_list: 0,
+            pages: 0,
+            in_use: 0,
+            free: 0,
+        }
+    }
+
+    fn pop(&mut self) -> Option<*mut u8> {
+        if self.free_list == 0 {
+            return None;
+        }
+        let object = self.free_list;
+        self.free_list = unsafe { *(object as *const usize) };
+        self.free -= 1;
+        self.in_use += 1;
+        Some(object as *mut u8)
+    }
+
+    fn push(&mut self, object: *mut u8) {
+        unsafe { *(object as *mut usize) = self.free_list };
+        self.free_list = object as usize;
+        self.free += 1;
+    }
+
+    /// 把一个新的页帧切成对象放到空闲链表中
+    fn add_page(&mut self, ppn: PhysPageNum) {
+        let base = ppn.0 * PAGE_SIZE;
+        for offset in (0..PAGE_SIZE).step_by(self.object_size).rev() {
+            self.push((base + offset) as *mut u8);
+        }
+        self.pages += 1;
+    }
+}
+
+/// 一个cache的统计信息
+pub struct SlabStat {
+    pub object_size: usize,
+    /// cache占用的页帧数
+    pub pages: usize,
+    /// 已经分配出去的对象数
+    pub in_use: usize,
+    /// 空闲链表中的对象数
+    pub free: usize,
+}
+
+/// 页帧分配器初始化之前slab不能从它那里拿页帧，这时的分配全部交给内核堆
+static SLAB_READY: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    // 不能用Vec，初始化时的分配会再次进入slab
+    static ref SLAB_CACHES: [UPSafeCell<SlabCache>; SLAB_SIZES.len()] =
+        SLAB_SIZES.map(|size| UPSafeCell::new(SlabCache::new(size)));
+}
+
+pub fn init_slab() {
+    SLAB_READY.store(true, Ordering::Relaxed);
+}
+
+/// layout对应的cache下标，太大的对象返回None
+fn cache_index(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    SLAB_SIZES
+        .iter()
+        .position(|&object_size| size <= object_size)
+}
+
+/// 从slab分配一个对象，返回None时调用者应该改用内核堆
+pub fn slab_alloc(layout: Layout) -> Option<*mut u8> {
+    if !SLAB_READY.load(Ordering::Relaxed) {
+        return None;
+    }
+    let cache = &SLAB_CACHES[cache_index(layout)?];
+    if let Some(object) = cache.exclusive_access().pop() {
+        return Some(object);
+    }
+    // 分配页帧时页帧分配器自己也可能分配内存，从而再次进入slab，所以这时不能借用cache
+    let ppn = try_frame_alloc_raw()?;
+    let mut cache = cache.exclusive_access();
+    cache.add_page(ppn);
+    cache.pop()
+}
+
+/// 把slab_alloc分配的对象还给对应的cache，页帧不会还给页帧分配器
+pub fn slab_dealloc(object: *mut u8, layout: Layout) {
+    let index = cache_index(layout).expect("object is not allocated by slab");
+    let mut cache = SLAB_CACHES[index].exclusive_access();
+    cache.in_use -= 1;
+    cache.push(object);
+}
+
+/// 各个cache的统计信息
+pub fn slab_stats() -> Vec<SlabStat> {
+    // 先分配好空间，借用cache的时候不能再分配内存
+    let mut stats = Vec::with_capacity(SLAB_CACHES.len());
+    for cache in SLAB_CACHES.iter() {
+        let cache = cache.exclusive_access();
+        stats.push(SlabStat {
+            object_size: cache.object_size,
+            pages: cache.pages,
+            in_use: cache.in_use,
+            free: cache.free,
+        });
+    }
+    stats
+}
+
+#[allow(unused)]
+pub fn slab_test() {
+    use crate::println;
+    use alloc::boxed::Box;
+    let before = slab_stats();
+    let objects: Vec<Box<[u8; 100]>> = (0..100).map(|i| Box::new([i as u8; 100])).collect();
+    for (i, object) in objects.iter().enumerate() {
+        assert!(object.iter().all(|&x| x == i as u8));
+        assert_eq!(object.as_ptr() as usize % 128, 0);
+    }
+    let after = slab_stats();
+    // [u8; 100]在128字节的cache中
+    assert!(after[3].in_use >= before[3].in_use + 100);
+    drop(objects);
+    assert_eq!(slab_stats()[3].in_use, before[3].in_use);
+    println!("slab_test passed!");
+}
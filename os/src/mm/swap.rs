@@ -0,0 +1,91 @@
+//! 交换区：内存不够时把用户页面换出到virtio-blk块设备上，缺页时再换入
+
+use super::address::PhysPageNum;
+use crate::config::{PAGE_SIZE, SWAP_PAGES};
+use crate::drivers::block::{BLOCK_DEVICE, BLOCK_SZ};
+use crate::println;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+// 每个交换槽保存一个页面，占用连续的BLOCKS_PER_SLOT个块
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BLOCK_SZ;
+
+// 和StackFrameAllocator一样的分配方式
+struct SwapSlotAllocator {
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl SwapSlotAllocator {
+    fn new() -> Self {
+        Self {
+            current: 0,
+            end: SWAP_PAGES,
+            recycled: Vec::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(slot) = self.recycled.pop() {
+            Some(slot)
+        } else if self.current < self.end {
+            self.current += 1;
+            Some(self.current - 1)
+        } else {
+            None
+        }
+    }
+
+    fn dealloc(&mut self, slot: usize) {
+        if slot >= self.current || self.recycled.iter().any(|&v| v == slot) {
+            panic!("Swap slot {} has not been allocated!", slot);
+        }
+        self.recycled.push(slot);
+    }
+}
+
+lazy_static! {
+    static ref SWAP_SLOT_ALLOCATOR: UPSafeCell<SwapSlotAllocator> =
+        UPSafeCell::new(SwapSlotAllocator::new());
+}
+
+/// 初始化交换区所在的块设备
+pub fn init_swap() {
+    lazy_static::initialize(&BLOCK_DEVICE);
+    println!("[kernel] Swap: {} pages on virtio-blk", SWAP_PAGES);
+}
+
+/// 交换区的总页数和已经使用的页数
+pub fn swap_stats() -> (usize, usize) {
+    let allocator = SWAP_SLOT_ALLOCATOR.exclusive_access();
+    (allocator.end, allocator.current - allocator.recycled.len())
+}
+
+/// RAII的交换槽，drop时自动回收
+pub struct SwapSlot(usize);
+
+impl SwapSlot {
+    /// 把物理页帧ppn中的数据写到一个新的交换槽中，交换区满了返回None
+    pub fn swap_out(ppn: PhysPageNum) -> Option<Self> {
+        let slot = SWAP_SLOT_ALLOCATOR.exclusive_access().alloc()?;
+        for (i, buf) in ppn.get_bytes_array().chunks(BLOCK_SZ).enumerate() {
+            BLOCK_DEVICE.write_block(slot * BLOCKS_PER_SLOT + i, buf);
+        }
+        Some(Self(slot))
+    }
+
+    /// 把交换槽中的数据读到物理页帧ppn中
+    pub fn read(&self, ppn: PhysPageNum) {
+        for (i, buf) in ppn.get_bytes_array().chunks_mut(BLOCK_SZ).enumerate() {
+            BLOCK_DEVICE.read_block(self.0 * BLOCKS_PER_SLOT + i, buf);
+        }
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_SLOT_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
@@ -0,0 +1,160 @@
+//! 内核访问用户内存的接口
+//!
+//! 用户传进来的指针都不可信，每一页都要按所在MapArea的U位和读写权限检查，
+//! 再处理懒分配、写时复制和换出，不能访问时返回UserFault，由系统调用转成-EFAULT。
+//! 内核写用户内存也要经过这里，否则用户程序可以借系统调用改写自己的.text和.rodata
+
+use super::address::{VirtAddr, VirtPageNum};
+use super::memory_set::{MapPermission, MemorySet};
+use crate::config::{PAGE_SIZE, USER_MMAP_END};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+/// 用户传入的地址不能按要求访问
+#[derive(Debug)]
+pub struct UserFault;
+
+/// 把用户地址空间中的[start, start + len)按页切开，返回每一页对应的内核可访问的切片
+/// access只能是MapPermission::R或MapPermission::W
+fn user_buffers(
+    memory_set: &mut MemorySet,
+    start: usize,
+    len: usize,
+    access: MapPermission,
+) -> Result<Vec<&'static mut [u8]>, UserFault> {
+    let end = start.checked_add(len).ok_or(UserFault)?;
+    // 用户地址空间在Sv39的低半部分，更高的地址会被VirtAddr截断成别的地址
+    if end > USER_MMAP_END {
+        return Err(UserFault);
+    }
+    let mut buffers = Vec::new();
+    let mut current = start;
+    while current < end {
+        let va = VirtAddr::from(current);
+        let vpn = va.floor();
+        if !memory_set.user_accessible(vpn, access) {
+            return Err(UserFault);
+        }
+        // 内核通过物理地址访问用户内存不会触发缺页异常，要先把这一页准备好，
+        // 写的时候写时复制的页面会在这里复制一份
+        memory_set.handle_page_fault(va, access);
+        let pte = match memory_set.translate(vpn) {
+            Some(pte) if pte.is_valid() && pte.is_user() => pte,
+            _ => return Err(UserFault),
+        };
+        // 页表项应该已经和MapArea的权限一致了，这里再检查一次
+        if (access == MapPermission::W && !pte.writable()) || !pte.readable() {
+            return Err(UserFault);
+        }
+        let page_end = usize::from(VirtAddr::from(VirtPageNum(vpn.0 + 1))).min(end);
+        let offset = va.page_offset();
+        buffers.push(&mut pte.ppn().get_bytes_array()[offset..offset + (page_end - current)]);
+        current = page_end;
+    }
+    Ok(buffers)
+}
+
+/// 用户地址空间中的一段字节
+pub struct UserSlice {
+    addr: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(addr: usize, len: usize) -> Self {
+        Self { addr, len }
+    }
+
+    /// 检查这段内存可读，返回按页切开的切片
+    pub fn buffers(&self, memory_set: &mut MemorySet) -> Result<Vec<&'static [u8]>, UserFault> {
+        let buffers = user_buffers(memory_set, self.addr, self.len, MapPermission::R)?;
+        Ok(buffers.into_iter().map(|buf| &*buf).collect())
+    }
+
+    /// 检查这段内存可写，返回按页切开的切片
+    pub fn buffers_mut(
+        &self,
+        memory_set: &mut MemorySet,
+    ) -> Result<Vec<&'static mut [u8]>, UserFault> {
+        user_buffers(memory_set, self.addr, self.len, MapPermission::W)
+    }
+
+    /// 把src复制到这段内存中，src的长度要和这段内存一样
+    pub fn copy_from(&self, memory_set: &mut MemorySet, src: &[u8]) -> Result<(), UserFault> {
+        assert_eq!(src.len(), self.len);
+        let mut start = 0;
+        for buf in self.buffers_mut(memory_set)? {
+            buf.copy_from_slice(&src[start..start + buf.len()]);
+            start += buf.len();
+        }
+        Ok(())
+    }
+
+    /// 把这段内存复制到dst中，dst的长度要和这段内存一样
+    pub fn copy_to(&self, memory_set: &mut MemorySet, dst: &mut [u8]) -> Result<(), UserFault> {
+        assert_eq!(dst.len(), self.len);
+        let mut start = 0;
+        for buf in self.buffers(memory_set)? {
+            dst[start..start + buf.len()].copy_from_slice(buf);
+            start += buf.len();
+        }
+        Ok(())
+    }
+}
+
+/// 指向用户地址空间中一个T的指针，按字节复制，不要求对齐
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 往后偏移count个T，和指针的add一样
+    pub fn add(&self, count: usize) -> Self {
+        Self::new(self.addr.wrapping_add(count.wrapping_mul(size_of::<T>())))
+    }
+
+    pub fn read(&self, memory_set: &mut MemorySet) -> Result<T, UserFault> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let dst = unsafe {
+            core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>())
+        };
+        UserSlice::new(self.addr, size_of::<T>()).copy_to(memory_set, dst)?;
+        Ok(unsafe { value.assume_init() })
+    }
+
+    pub fn write(&self, memory_set: &mut MemorySet, value: T) -> Result<(), UserFault> {
+        let src =
+            unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        UserSlice::new(self.addr, size_of::<T>()).copy_from(memory_set, src)
+    }
+}
+
+/// 读取用户地址空间中以0结尾的字符串
+pub fn read_user_str(memory_set: &mut MemorySet, addr: usize) -> Result<String, UserFault> {
+    let mut string = String::new();
+    let mut current = addr;
+    loop {
+        // 一次检查到页尾，字符串后面的页面不一定能访问
+        let page_end = (current / PAGE_SIZE + 1) * PAGE_SIZE;
+        let buffers = UserSlice::new(current, page_end - current).buffers(memory_set)?;
+        let buf = buffers[0];
+        match buf.iter().position(|&ch| ch == 0) {
+            Some(len) => {
+                string.extend(buf[..len].iter().map(|&ch| ch as char));
+                return Ok(string);
+            }
+            None => string.extend(buf.iter().map(|&ch| ch as char)),
+        }
+        current = page_end;
+    }
+}
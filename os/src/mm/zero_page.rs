@@ -0,0 +1,27 @@
+//! 全0的共享页帧
+//!
+//! 懒分配的页面（.bss、堆、mmap的匿名内存）第一次被读时不分配新的页帧，
+//! 而是只读地映射到这个全0的页帧上，第一次写时再按写时复制分配一个私有的页帧
+
+use super::address::PhysPageNum;
+use super::frame_allocator::{frame_alloc, FrameTracker};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref ZERO_FRAME: FrameTracker = frame_alloc().unwrap();
+}
+
+/// 返回指向全0页帧的一个句柄，映射到地址空间中时不能有写权限
+pub fn zero_frame() -> FrameTracker {
+    ZERO_FRAME.clone()
+}
+
+pub fn is_zero_frame(ppn: PhysPageNum) -> bool {
+    ZERO_FRAME.ppn == ppn
+}
+
+/// 映射到全0页帧的页面数，也就是因此少分配的页帧数
+pub fn zero_page_stats() -> usize {
+    // 除了ZERO_FRAME自己，每个句柄都对应一个映射
+    ZERO_FRAME.ref_count() - 1
+}
@@ -0,0 +1,23 @@
+//! 内核的随机数来源
+//!
+//! 没有硬件随机数发生器，熵来自time CSR：第一次使用时用当前时间作为种子，
+//! 之后每次取随机数时再混入当前时间，所以程序启动的时机不同，得到的随机数也不同。
+//! 这只够用来做地址随机化，不能用于密码学
+
+use crate::sync::UPSafeCell;
+use crate::timer::get_time;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref RANDOM_STATE: UPSafeCell<u64> = UPSafeCell::new(get_time() as u64);
+}
+
+/// 用splitmix64生成一个64位的随机数
+pub fn random_u64() -> u64 {
+    let mut state = RANDOM_STATE.exclusive_access();
+    *state = (*state ^ get_time() as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -24,6 +24,11 @@ impl<T> UPSafeCell<T> {
     pub fn exclusive_access(&self) -> RefMut<'_, T> {
         self.inner.borrow_mut()
     }
+
+    /// 和exclusive_access一样，但数据已经被借用时返回None而不是panic
+    pub fn try_exclusive_access(&self) -> Option<RefMut<'_, T>> {
+        self.inner.try_borrow_mut().ok()
+    }
 }
 
 // 在rust中，当你解引用时，如果数据实现了Copy trait（i32等基础数据实现了，但是String这种类型没有），将会进行拷贝，否则将会进行move
@@ -1,9 +1,10 @@
 //! File and filesystem-related syscalls
+use super::EFAULT;
 use crate::{
-    mm::translate_buffer,
+    mm::{UserPtr, UserSlice},
     print,
     sbi::console_getchar,
-    task::{current_user_token, suspend_current_and_run_next},
+    task::{current_process, suspend_current_and_run_next},
 };
 
 const FD_STDIN: usize = 0;
@@ -14,7 +15,13 @@ const FD_STDOUT: usize = 1;
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT => {
-            let translated_pages = translate_buffer(current_user_token(), buf, len);
+            let process = current_process();
+            let mut inner = process.inner_exclusive_access();
+            let translated_pages =
+                match UserSlice::new(buf as usize, len).buffers(&mut inner.memory_set) {
+                    Ok(buffers) => buffers,
+                    Err(_) => return EFAULT,
+                };
 
             for slice in translated_pages {
                 let utf8_str = core::str::from_utf8(slice).unwrap();
@@ -36,6 +43,13 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
                 len, 1,
                 "Only support len = 1 in sys_read(FD_STDIN, buf, len)"
             );
+            // 先检查缓冲区，不要读走一个字符之后才发现写不进去
+            if UserSlice::new(buf as usize, len)
+                .buffers_mut(&mut current_process().inner_exclusive_access().memory_set)
+                .is_err()
+            {
+                return EFAULT;
+            }
             let mut c: usize = 0;
             loop {
                 c = console_getchar();
@@ -47,11 +61,14 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
                 }
             }
             let ch = c as u8;
-            let mut buffers = translate_buffer(current_user_token(), buf, len);
-            unsafe {
-                buffers[0].as_mut_ptr().write_volatile(ch);
+            // 等待输入的时候页面可能被换出，所以写入时还要再检查一次
+            match UserPtr::new(buf as usize).write(
+                &mut current_process().inner_exclusive_access().memory_set,
+                ch,
+            ) {
+                Ok(()) => 1,
+                Err(_) => EFAULT,
             }
-            1
         }
         _ => {
             panic!("Unsupport file descriptor: {}", fd);
@@ -2,12 +2,18 @@ use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+use super::{E2BIG, EFAULT, ENOEXEC, ENOMEM};
+use crate::config::{ARG_MAX, PAGE_SIZE, USER_MMAP_BASE, USER_MMAP_END};
 use crate::loader::get_app_data_by_name;
-use crate::mm::{translate_ref, translate_ref_mut, translate_str};
+use crate::mm::{
+    free_frame_count, heap_stats, read_user_str, shm_frames, shm_get, shm_remove, slab_stats,
+    swap_stats, total_frame_count, zero_page_stats, ElfError, MapPermission, MemorySet, UserPtr,
+};
 use crate::println;
 use crate::task::{
-    add_task, current_process, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next,
+    add_task, current_process, current_task, exit_current_and_run_next, get_process_from_pid,
+    process_list, reclaim_frames, send_signal, suspend_current_and_run_next, SignalAction,
+    SignalFlags,
 };
 use crate::timer::get_time;
 
@@ -30,8 +36,12 @@ pub fn sys_get_time() -> isize {
 // !
 pub fn sys_fork() -> isize {
     // 创建新进程
+    reclaim_frames();
     let process = current_process();
-    let new_process = process.fork();
+    let new_process = match process.fork() {
+        Some(new_process) => new_process,
+        None => return ENOMEM,
+    };
     let new_pid = new_process.getpid();
     // 修改trap context
     let new_trap_cx = new_process
@@ -47,29 +57,65 @@ pub fn sys_fork() -> isize {
     new_pid as isize
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
-    let token = current_user_token();
-    let path = translate_str(token, path);
-    let mut args_vec: Vec<String> = Vec::new();
-    
-    // 支持args的话，user_shell要大改，暂时先不弄了
-    // loop {
-    //     let arg_str_ptr = *translate_ref(token, args);
-    //     if arg_str_ptr == 0 {
-    //         break;
-    //     }
-    //     args_vec.push(translate_str(token, arg_str_ptr as *const u8));
-    //     unsafe {
-    //         args = args.add(1);
-    //     }
-    // }
+/// 读取用户传入的以空指针结尾的字符串指针数组，array为0时表示空数组
+/// total累计已经读到的字符串和指针占用的字节数，超过ARG_MAX时返回E2BIG
+fn read_user_str_array(
+    memory_set: &mut MemorySet,
+    array: usize,
+    total: &mut usize,
+) -> Result<Vec<String>, isize> {
+    let mut strings = Vec::new();
+    if array == 0 {
+        return Ok(strings);
+    }
+    let array = UserPtr::<usize>::new(array);
+    loop {
+        let ptr = array
+            .add(strings.len())
+            .read(memory_set)
+            .map_err(|_| EFAULT)?;
+        if ptr == 0 {
+            return Ok(strings);
+        }
+        let string = read_user_str(memory_set, ptr).map_err(|_| EFAULT)?;
+        *total += string.len() + 1 + core::mem::size_of::<usize>();
+        if *total > ARG_MAX {
+            return Err(E2BIG);
+        }
+        strings.push(string);
+    }
+}
+
+/// args和envs都是以空指针结尾的字符串指针数组，成功时返回参数个数
+pub fn sys_exec(path: *const u8, args: *const usize, envs: *const usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let memory_set = &mut inner.memory_set;
+    let path = match read_user_str(memory_set, path as usize) {
+        Ok(path) => path,
+        Err(_) => return EFAULT,
+    };
+    // 新程序的地址空间建好之前就要把参数复制出来，之后原来的地址空间就不存在了
+    let mut total = 0;
+    let args_vec = match read_user_str_array(memory_set, args as usize, &mut total) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
+    let envs_vec = match read_user_str_array(memory_set, envs as usize, &mut total) {
+        Ok(envs) => envs,
+        Err(err) => return err,
+    };
+    drop(inner);
 
     if let Some(data) = get_app_data_by_name(path.as_str()) {
-        let process = current_process();
+        reclaim_frames();
         let argc = args_vec.len();
-        process.exec(data, args_vec);
         // println!("try to exec {:?}", path);
-        argc as isize
+        match process.exec(data, args_vec, envs_vec) {
+            Ok(()) => argc as isize,
+            Err(ElfError::NoMemory) => ENOMEM,
+            Err(_) => ENOEXEC,
+        }
     } else {
         -1
     }
@@ -83,7 +129,8 @@ pub fn sys_getpid() -> isize {
 /// 如果ipid为-1，则等待任意子进程
 /// 如果不存在pid为ipid的子进程，则返回-1
 /// 如果存在pid为ipid的子进程，但其不是僵尸进程（还在运行），则返回-2
-pub fn sys_waitpid(ipid: isize, exit_code_ptr: *mut i32) -> isize {
+/// signal_ptr不为空时写入结束子进程的信号，正常退出时为0
+pub fn sys_waitpid(ipid: isize, exit_code_ptr: *mut i32, signal_ptr: *mut i32) -> isize {
     let process = current_process();
 
     let mut inner = process.inner_exclusive_access();
@@ -103,39 +150,359 @@ pub fn sys_waitpid(ipid: isize, exit_code_ptr: *mut i32) -> isize {
         .enumerate()
         .find(|(_, p)| p.is_zombie() && (ipid == -1 || ipid as usize == p.getpid()));
     if let Some((idx, _)) = pair {
+        let (exit_code, signal) = {
+            let child_inner = inner.children[idx].inner_exclusive_access();
+            (child_inner.exit_code, child_inner.signals.killed.unwrap_or(0) as i32)
+        };
+        // 注意！这里不能用current_user_token()之类的函数 -> de了半个小时bug的血泪
+        // 因为上面我们已经borrow了inner，再次borrow会造成borrow twice崩溃
+        // 先写退出码，写不进去时子进程仍然留着，之后还可以再等待
+        if UserPtr::new(exit_code_ptr as usize)
+            .write(&mut inner.memory_set, exit_code)
+            .is_err()
+        {
+            return EFAULT;
+        }
+        if !signal_ptr.is_null()
+            && UserPtr::new(signal_ptr as usize)
+                .write(&mut inner.memory_set, signal)
+                .is_err()
+        {
+            return EFAULT;
+        }
         let child = inner.children.remove(idx);
         // 确保离开此函数后child会被释放
         assert_eq!(Arc::strong_count(&child), 1);
+        child.getpid() as isize
+    } else {
+        -2
+    }
+}
+
+/// 给pid进程发送信号signum，signum为0时只检查进程是否存在
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    let process = match get_process_from_pid(pid) {
+        Some(process) => process,
+        None => return -1,
+    };
+    if signum == 0 {
+        return 0;
+    }
+    match SignalFlags::from_signum(signum) {
+        Some(signal) => {
+            send_signal(&process, signal);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// 设置信号signum的处理方式，action或old_action为0时不设置或不返回
+/// SIGKILL和SIGSTOP的处理方式不能修改
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    match SignalFlags::from_signum(signum) {
+        Some(signal) if signal.catchable() => {}
+        _ => return -1,
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old = inner.signals.actions[signum];
+    if !old_action.is_null()
+        && UserPtr::new(old_action as usize)
+            .write(&mut inner.memory_set, old)
+            .is_err()
+    {
+        return EFAULT;
+    }
+    if !action.is_null() {
+        let action = UserPtr::<SignalAction>::new(action as usize);
+        let mut new = match action.read(&mut inner.memory_set) {
+            Ok(new) => new,
+            Err(_) => return EFAULT,
+        };
+        new.mask = SignalFlags::from_bits_truncate(new.mask.bits());
+        inner.signals.actions[signum] = new;
+    }
+    0
+}
+
+/// 设置屏蔽的信号集合，返回原来的集合
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old = inner.signals.blocked;
+    // SIGKILL和SIGSTOP不能被屏蔽
+    let mut blocked = SignalFlags::from_bits_truncate(mask);
+    blocked.remove(SignalFlags::SIGKILL | SignalFlags::SIGSTOP);
+    inner.signals.blocked = blocked;
+    old.bits() as isize
+}
 
-        let found_pid = child.getpid();
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // 注意！这里传入的token不能用current_user_token()函数来获得 -> de了半个小时bug的血泪
-        // 因为上面我们已经borrow了inner，而current_user_token()会再次borrow，造成borrow twice崩溃
-        *translate_ref_mut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+/// 信号handler执行完之后调用，恢复执行handler之前的TrapContext
+pub fn sys_sigreturn() -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.trap_cx_backup.take() {
+        Some(backup) => {
+            inner.handling_signal = None;
+            let trap_cx = inner.get_trap_cx();
+            *trap_cx = backup;
+            // 系统调用的返回值会写到a0中，这里返回原来的a0
+            trap_cx.x[10] as isize
+        }
+        None => -1,
+    }
+}
 
-        found_pid as isize
+/// 改变program break，成功返回旧的program break，失败返回-1
+pub fn sys_sbrk(size: i32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if let Some(old_brk) = inner.change_program_brk(size) {
+        old_brk as isize
     } else {
-        -2
+        -1
+    }
+}
+
+/// 把mmap/mprotect的prot参数转换成MapPermission
+/// prot的第0位为R，第1位为W，第2位为X，其他位必须为0，且至少要有一个权限
+fn prot_to_permission(prot: usize) -> Option<MapPermission> {
+    if prot & !0x7 != 0 || prot & 0x7 == 0 {
+        return None;
+    }
+    let mut perm = MapPermission::U;
+    // RISC-V的页表项不允许只写不读，所以W也意味着R
+    if prot & 0x3 != 0 {
+        perm |= MapPermission::R;
+    }
+    if prot & 0x2 != 0 {
+        perm |= MapPermission::W;
+    }
+    if prot & 0x4 != 0 {
+        perm |= MapPermission::X;
+    }
+    Some(perm)
+}
+
+/// 匿名私有映射一段内存，start为0时由内核选择地址
+/// 成功返回映射的起始地址，失败返回-1
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    if len == 0 || start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let perm = match prot_to_permission(prot) {
+        Some(perm) => perm,
+        None => return -1,
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.memory_set.mmap(start, len, perm) {
+        Some(start) => start as isize,
+        None => -1,
+    }
+}
+
+/// 取消一段由mmap映射的内存，成功返回0，失败返回-1
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    if len == 0 || start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.munmap(start, len) {
+        0
+    } else {
+        -1
     }
 }
 
-// pub fn sys_kill(pid: usize, signal: u32) -> isize {
-//     if let Some(process) = pid2process(pid) {
-//         if let Some(flag) = SignalFlags::from_bits(signal) {
-//             process.inner_exclusive_access().signals |= flag;
-//             0
-//         } else {
-//             -1
-//         }
-//     } else {
-//         -1
-//     }
-// }
-
-// pub fn sys_sbrk(size: i32) -> isize {
-//     if let Some(old_brk) = change_program_brk(size) {
-//         old_brk as isize
-//     } else {
-//         -1
-//     }
-// }
+/// 修改一段由mmap映射的内存的权限，成功返回0，失败返回-1
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    if len == 0 || start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let perm = match prot_to_permission(prot) {
+        Some(perm) => perm,
+        None => return -1,
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.mprotect(start, len, perm) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// 获取key对应的共享内存段，不存在时创建一个len字节的新段
+/// key为0时总是创建新段，成功返回共享内存段的id，失败返回-1
+pub fn sys_shmget(key: usize, len: usize) -> isize {
+    if len == 0 || len > USER_MMAP_END - USER_MMAP_BASE {
+        return -1;
+    }
+    match shm_get(key, len) {
+        Some(id) => id as isize,
+        None => -1,
+    }
+}
+
+/// 把共享内存段id映射到start处，start为0时由内核选择地址
+/// 成功返回映射的起始地址，失败返回-1
+pub fn sys_shmat(id: usize, start: usize) -> isize {
+    if start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let frames = match shm_frames(id) {
+        Some(frames) => frames,
+        None => return -1,
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.memory_set.attach_shm(start, frames) {
+        Some(start) => start as isize,
+        None => -1,
+    }
+}
+
+/// 取消起始地址为start的共享内存映射，成功返回0，失败返回-1
+pub fn sys_shmdt(start: usize) -> isize {
+    if start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.detach_shm(start) {
+        0
+    } else {
+        -1
+    }
+}
+
+const IPC_RMID: usize = 0;
+
+/// 控制共享内存段id，目前只支持IPC_RMID（删除）
+/// 删除后id不再可用，但已经建立的映射在取消之前仍然有效
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    match cmd {
+        IPC_RMID if shm_remove(id) => 0,
+        _ => -1,
+    }
+}
+
+/// 系统的内存使用情况，页面的单位都是页帧，堆的单位是字节
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MemInfo {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    pub swap_total_pages: usize,
+    pub swap_used_pages: usize,
+    pub kernel_heap_total: usize,
+    pub kernel_heap_used: usize,
+    /// 映射到共享全0页帧而少分配的页帧数
+    pub zero_page_saved: usize,
+}
+
+/// 一个进程的内存使用情况
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ProcInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    pub threads: usize,
+    pub resident_pages: usize,
+    pub swapped_pages: usize,
+}
+
+/// 把系统的内存使用情况写到info中，成功返回0
+pub fn sys_meminfo(info: *mut MemInfo) -> isize {
+    let (swap_total_pages, swap_used_pages) = swap_stats();
+    let (kernel_heap_total, kernel_heap_used) = heap_stats();
+    let meminfo = MemInfo {
+        total_frames: total_frame_count(),
+        free_frames: free_frame_count(),
+        swap_total_pages,
+        swap_used_pages,
+        kernel_heap_total,
+        kernel_heap_used,
+        zero_page_saved: zero_page_stats(),
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match UserPtr::new(info as usize).write(&mut inner.memory_set, meminfo) {
+        Ok(()) => 0,
+        Err(_) => EFAULT,
+    }
+}
+
+/// 把各个进程的内存使用情况写到buf中，最多写count个
+/// 返回进程总数，大于count说明buf不够大
+pub fn sys_procinfo(buf: *mut ProcInfo, count: usize) -> isize {
+    let processes = process_list();
+    let infos: Vec<ProcInfo> = processes
+        .iter()
+        .map(|process| {
+            let inner = process.inner_exclusive_access();
+            ProcInfo {
+                pid: process.getpid(),
+                ppid: inner
+                    .parent
+                    .as_ref()
+                    .and_then(|parent| parent.upgrade())
+                    .map_or(0, |parent| parent.getpid()),
+                threads: inner.tasks.iter().filter(|task| task.is_some()).count(),
+                resident_pages: inner.memory_set.resident_pages(),
+                swapped_pages: inner.memory_set.swapped_pages(),
+            }
+        })
+        .collect();
+    let total = infos.len();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let buf = UserPtr::new(buf as usize);
+    for (i, info) in infos.into_iter().take(count).enumerate() {
+        if buf.add(i).write(&mut inner.memory_set, info).is_err() {
+            return EFAULT;
+        }
+    }
+    total as isize
+}
+
+/// 一个slab cache的使用情况
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SlabInfo {
+    pub object_size: usize,
+    pub pages: usize,
+    pub in_use: usize,
+    pub free: usize,
+}
+
+/// 把内核各个slab cache的使用情况写到buf中，最多写count个
+/// 返回cache总数，大于count说明buf不够大
+pub fn sys_slabinfo(buf: *mut SlabInfo, count: usize) -> isize {
+    let infos: Vec<SlabInfo> = slab_stats()
+        .into_iter()
+        .map(|stat| SlabInfo {
+            object_size: stat.object_size,
+            pages: stat.pages,
+            in_use: stat.in_use,
+            free: stat.free,
+        })
+        .collect();
+    let total = infos.len();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let buf = UserPtr::new(buf as usize);
+    for (i, info) in infos.into_iter().take(count).enumerate() {
+        if buf.add(i).write(&mut inner.memory_set, info).is_err() {
+            return EFAULT;
+        }
+    }
+    total as isize
+}
@@ -33,14 +33,17 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
         tasks.push(None);
     }
     tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    // 每个线程有自己的线程局部存储
+    let (user_sp, tp) = process_inner.push_tls(new_task_res.ustack_top());
     let new_task_trap_cx = new_task_inner.get_trap_cx();
     *new_task_trap_cx = TrapContext::app_init_context(
         entry,
-        new_task_res.ustack_top(),
+        user_sp,
         kernel_token(),
         new_task.kstack.get_top(),
         trap_handler as usize,
     );
+    (*new_task_trap_cx).x[4] = tp;
     (*new_task_trap_cx).x[10] = arg;
     new_task_tid as isize
 }
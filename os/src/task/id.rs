@@ -6,9 +6,10 @@ use lazy_static::lazy_static;
 
 use crate::{
     config::{
-        KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE_ADDRESS, TRAP_CONTEXT_ADDRESS, USER_STACK_SIZE,
+        KERNEL_STACK_SIZE, PAGE_SIZE, STACK_GUARD_GAP, TRAMPOLINE_ADDRESS, TRAP_CONTEXT_ADDRESS,
+        USER_STACK_LIMIT, USER_STACK_SIZE,
     },
-    mm::{MapPermission, PhysPageNum, VirtAddr, KERNEL_SPACE},
+    mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE},
     println,
     sync::UPSafeCell,
 };
@@ -46,8 +47,9 @@ fn trap_cx_bottom_from_tid(tid: usize) -> usize {
     TRAP_CONTEXT_ADDRESS - tid * PAGE_SIZE
 }
 
+/// 线程的用户栈最多能增长到的最低地址，下面还有STACK_GUARD_GAP大小的保护区间
 fn ustack_bottom_from_tid(ustack_base: usize, tid: usize) -> usize {
-    ustack_base + tid * (PAGE_SIZE + USER_STACK_SIZE)
+    ustack_base + tid * (STACK_GUARD_GAP + USER_STACK_LIMIT)
 }
 
 impl TaskUserRes {
@@ -86,25 +88,35 @@ impl TaskUserRes {
     }
 
     pub fn ustack_top(&self) -> usize {
-        ustack_bottom_from_tid(self.ustack_base, self.tid) + USER_STACK_SIZE
+        ustack_bottom_from_tid(self.ustack_base, self.tid) + USER_STACK_LIMIT
     }
 
     pub fn ustack_base(&self) -> usize {
         self.ustack_base
     }
 
+    /// 把这个线程的用户栈当作0号线程的用户栈时对应的ustack_base
+    /// fork出的子进程只有调用fork的线程，它在子进程中成为0号线程，用户栈的位置不变
+    pub fn ustack_base_as_main(&self) -> usize {
+        ustack_bottom_from_tid(self.ustack_base, self.tid)
+    }
+
     /// 在进程地址空间中映射线程的用户栈和 Trap 上下文。
     pub fn alloc_user_res(&self) {
         let process = self.process.upgrade().unwrap();
         let mut process_inner = process.inner_exclusive_access();
 
         // alloc ustack
+        // 用户栈是懒分配的，只有用到的页面才会分配物理页帧
+        // 一开始只有USER_STACK_SIZE大小，访问到下方时再向下增长到ustack_bottom
         let ustack_bottom = ustack_bottom_from_tid(self.ustack_base, self.tid);
-        let ustack_top = ustack_bottom + USER_STACK_SIZE;
-        process_inner.memory_set.insert_framed_area(
+        let ustack_top = self.ustack_top();
+        let stack_perm = process_inner.stack_perm;
+        process_inner.memory_set.insert_stack_area(
             ustack_bottom.into(),
+            (ustack_top - USER_STACK_SIZE).into(),
             ustack_top.into(),
-            MapPermission::R | MapPermission::W | MapPermission::U,
+            stack_perm,
         );
 
         // alloc trap_cx
@@ -120,18 +132,20 @@ impl TaskUserRes {
     fn dealloc_user_res(&self) {
         let process = self.process.upgrade().unwrap();
         let mut process_inner = process.inner_exclusive_access();
+        self.remove_ustack(&mut process_inner.memory_set);
+        self.remove_trap_cx(&mut process_inner.memory_set);
+    }
 
-        // dealloc ustack
-        let ustack_bottom_va: VirtAddr = ustack_bottom_from_tid(self.ustack_base, self.tid).into();
-        process_inner
-            .memory_set
-            .remove_area_with_start_vpn(ustack_bottom_va.into());
+    /// 从memory_set中去掉这个线程的用户栈
+    pub fn remove_ustack(&self, memory_set: &mut MemorySet) {
+        let ustack_top_va: VirtAddr = self.ustack_top().into();
+        memory_set.remove_area_with_end_vpn(ustack_top_va.into());
+    }
 
-        // dealloc trap_cx
+    /// 从memory_set中去掉这个线程的TrapContext
+    pub fn remove_trap_cx(&self, memory_set: &mut MemorySet) {
         let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
-        process_inner
-            .memory_set
-            .remove_area_with_start_vpn(trap_cx_bottom_va.into());
+        memory_set.remove_area_with_start_vpn(trap_cx_bottom_va.into());
     }
 
     pub fn dealloc_tid(&self) {
@@ -1,7 +1,10 @@
+use crate::config::{SWAP_BATCH, SWAP_LOW_WATERMARK};
+use crate::mm::free_frame_count;
 use crate::sync::UPSafeCell;
 use alloc::{
     collections::{btree_map::BTreeMap, vec_deque::VecDeque},
     sync::Arc,
+    vec::Vec,
 };
 use lazy_static::lazy_static;
 
@@ -64,6 +67,10 @@ pub fn remove_task(task: Arc<TaskControlBlock>) {
 
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
     let mut task_inner = task.inner_exclusive_access();
+    // 被信号提前唤醒的线程可能还留在等待队列中，不能再加入一次就绪队列
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
     add_task(task);
@@ -84,3 +91,36 @@ pub fn remove_from_pid2process(pid: usize) {
         panic!("cannot find pid {} in pid2task!", pid);
     }
 }
+
+/// 当前所有进程的列表
+pub fn process_list() -> Vec<Arc<ProcessControlBlock>> {
+    PID2PCB_LOOKUP
+        .exclusive_access()
+        .values()
+        .cloned()
+        .collect()
+}
+
+/// 空闲物理页帧少于SWAP_LOW_WATERMARK时，轮流从各个进程中换出一批页面，
+/// 调用者正在借用的进程（比如正在处理缺页的当前进程）会被跳过
+pub fn reclaim_frames() {
+    if free_frame_count() >= SWAP_LOW_WATERMARK {
+        return;
+    }
+    let processes = process_list();
+    loop {
+        let mut swapped = 0;
+        for process in processes.iter() {
+            if free_frame_count() >= SWAP_LOW_WATERMARK {
+                return;
+            }
+            if let Some(mut inner) = process.try_inner_exclusive_access() {
+                swapped += inner.memory_set.swap_out(SWAP_BATCH);
+            }
+        }
+        // 所有进程都没有页面可以换出了（或者交换区满了）
+        if swapped == 0 {
+            return;
+        }
+    }
+}
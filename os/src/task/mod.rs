@@ -3,12 +3,13 @@ mod id;
 mod manager;
 mod process;
 mod processor;
+mod signal;
 mod switch;
 mod task;
 
 use alloc::sync::Arc;
 pub use context::TaskContext;
-pub use manager::{add_task, wakeup_task};
+pub use manager::{add_task, get_process_from_pid, process_list, reclaim_frames, wakeup_task};
 use process::ProcessControlBlock;
 // pub use task::TaskStatus;
 
@@ -30,3 +31,4 @@ pub use processor::{
     block_current_and_run_next, current_process, current_task, current_trap_cx, current_user_token,
     exit_current_and_run_next, run_tasks, suspend_current_and_run_next,
 };
+pub use signal::{handle_signals, send_signal, SignalAction, SignalFlags};
@@ -4,21 +4,29 @@ use crate::sync::{Condvar, Mutex, Semaphore};
 use alloc::{
     string::String,
     sync::{Arc, Weak},
+    vec,
     vec::Vec,
 };
 
 use crate::{
-    config::TRAP_CONTEXT_ADDRESS,
-    mm::{translate_ref_mut, MemorySet, VirtAddr, KERNEL_SPACE},
+    config::{TRAP_CONTEXT_ADDRESS, USER_HEAP_LIMIT},
+    mm::{
+        ElfError, MapPermission, MemorySet, TlsTemplate, UserSlice, VirtAddr, AT_NULL, AT_RANDOM,
+        KERNEL_SPACE,
+    },
     print, println,
+    random::random_u64,
     sync::UPSafeCell,
     task::add_task,
+    timer::remove_timer,
     trap::{trap_handler, TrapContext},
 };
 
 use super::{
-    id::{pid_alloc, PidHandle, RecycleAllocator},
-    manager::insert_into_pid2process,
+    current_task,
+    id::{pid_alloc, PidHandle, RecycleAllocator, TaskUserRes},
+    manager::{insert_into_pid2process, remove_task},
+    signal::SignalState,
     task::TaskControlBlock,
 };
 
@@ -33,11 +41,20 @@ pub struct ProcessControlBlockInner {
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
     pub exit_code: i32,
+    /// 用户堆的基地址
+    pub heap_bottom: usize,
+    /// 当前的program break，即堆顶
+    pub program_brk: usize,
+    /// 线程用户栈的权限
+    pub stack_perm: MapPermission,
+    /// 每个线程都要有一份的线程局部存储
+    pub tls: Option<TlsTemplate>,
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
     pub task_res_allocator: RecycleAllocator,
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    pub signals: SignalState,
 }
 
 impl ProcessControlBlockInner {
@@ -56,6 +73,103 @@ impl ProcessControlBlockInner {
     pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
         self.tasks[tid].as_ref().unwrap().clone()
     }
+
+    /// 在user_sp下方放一份线程局部存储，返回新的user_sp和线程指针tp
+    /// 没有PT_TLS时tp为0
+    pub fn push_tls(&mut self, user_sp: usize) -> (usize, usize) {
+        let tls = match &self.tls {
+            Some(tls) => tls,
+            None => return (user_sp, 0),
+        };
+        // RISC-V的tp直接指向线程局部存储的开头，同时保证栈按16B对齐
+        let tp = (user_sp - tls.mem_size) & !(tls.align.max(16) - 1);
+        let mut block = tls.image.clone();
+        block.resize(tls.mem_size, 0);
+        UserSlice::new(tp, tls.mem_size)
+            .copy_from(&mut self.memory_set, &block)
+            .unwrap();
+        (tp, tp)
+    }
+
+    /// 在user_sp下方按Linux的格式放好程序的参数、环境变量和辅助向量，
+    /// 返回新的user_sp以及argv和envp的地址，用户栈的布局如下（地址从低到高）
+    /// | argc | argv[0..argc] | 0 | envp[..] | 0 | auxv | 字符串 | AT_RANDOM的16B | TLS |
+    pub fn push_args(
+        &mut self,
+        mut user_sp: usize,
+        args: &[String],
+        envs: &[String],
+        auxv: &[(usize, usize)],
+    ) -> (usize, usize, usize) {
+        // 用户栈是懒分配的，通过UserSlice写入时会先把要用到的页面分配好
+        let memory_set = &mut self.memory_set;
+        let mut push_bytes = |bytes: &[u8]| {
+            user_sp -= bytes.len();
+            UserSlice::new(user_sp, bytes.len())
+                .copy_from(memory_set, bytes)
+                .unwrap();
+            user_sp
+        };
+        // 给用户程序的随机数种子
+        let mut random = [0u8; 16];
+        random[..8].copy_from_slice(&random_u64().to_le_bytes());
+        random[8..].copy_from_slice(&random_u64().to_le_bytes());
+        let random_addr = push_bytes(&random);
+        let mut push_str = |string: &String| {
+            let mut bytes = string.as_bytes().to_vec();
+            bytes.push(0);
+            push_bytes(&bytes)
+        };
+        let envp: Vec<usize> = envs.iter().map(&mut push_str).collect();
+        let argv: Vec<usize> = args.iter().map(&mut push_str).collect();
+
+        let mut words = vec![args.len()];
+        words.extend(argv);
+        words.push(0);
+        words.extend(envp);
+        words.push(0);
+        // 辅助向量由(类型, 值)组成，以AT_NULL结尾
+        for &(key, value) in auxv {
+            words.extend([key, value]);
+        }
+        words.extend([AT_RANDOM, random_addr, AT_NULL, 0]);
+
+        // 保证user_sp按16B对齐，sp指向argc
+        let size = words.len() * core::mem::size_of::<usize>();
+        user_sp = (user_sp - size) & !0xf;
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        UserSlice::new(user_sp, size)
+            .copy_from(memory_set, &bytes)
+            .unwrap();
+        let argv_base = user_sp + core::mem::size_of::<usize>();
+        let envp_base = argv_base + (args.len() + 1) * core::mem::size_of::<usize>();
+        (user_sp, argv_base, envp_base)
+    }
+
+    /// 将program break移动size个字节，成功时返回旧的program break
+    pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
+        let old_brk = self.program_brk;
+        let new_brk = self.program_brk as isize + size as isize;
+        // 堆不能缩到基地址以下，也不能超过上限
+        if new_brk < self.heap_bottom as isize
+            || new_brk as usize > self.heap_bottom + USER_HEAP_LIMIT
+        {
+            return None;
+        }
+        let result = if size < 0 {
+            self.memory_set
+                .shrink_to(self.heap_bottom.into(), (new_brk as usize).into())
+        } else {
+            self.memory_set
+                .append_to(self.heap_bottom.into(), (new_brk as usize).into())
+        };
+        if result {
+            self.program_brk = new_brk as usize;
+            Some(old_brk)
+        } else {
+            None
+        }
+    }
 }
 
 impl ProcessControlBlock {
@@ -63,9 +177,15 @@ impl ProcessControlBlock {
         self.inner.exclusive_access()
     }
 
+    /// inner已经被借用时返回None
+    pub fn try_inner_exclusive_access(&self) -> Option<RefMut<'_, ProcessControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
+
     pub fn new(elf_data: &[u8]) -> Arc<Self> {
         // 解析elf文件
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, info) = MemorySet::from_elf(elf_data).expect("invalid initproc");
+        let auxv = info.auxv();
 
         // println!("try to new pcb");
         // 分配pid
@@ -80,18 +200,23 @@ impl ProcessControlBlock {
                 parent: None,
                 children: Vec::new(),
                 exit_code: 0,
+                heap_bottom: info.heap_bottom,
+                program_brk: info.heap_bottom,
+                stack_perm: info.stack_perm,
+                tls: info.tls,
                 tasks: Vec::new(),
                 task_res_allocator: RecycleAllocator::new(),
                 mutex_list: Vec::new(),
                 semaphore_list: Vec::new(),
                 condvar_list: Vec::new(),
+                signals: SignalState::new(),
             }),
         });
 
         // 创建主线程
         let main_task = Arc::new(TaskControlBlock::new(
             Arc::clone(&process),
-            ustack_base,
+            info.ustack_base,
             true,
         ));
 
@@ -101,13 +226,21 @@ impl ProcessControlBlock {
         let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
         let kstack_top = main_task.kstack.get_top();
         drop(task_inner);
+        let mut process_inner = process.inner_exclusive_access();
+        let (user_sp, tp) = process_inner.push_tls(ustack_top);
+        let (user_sp, argv_base, envp_base) = process_inner.push_args(user_sp, &[], &[], &auxv);
+        drop(process_inner);
         *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            ustack_top,
+            info.entry_point,
+            user_sp,
             KERNEL_SPACE.exclusive_access().token(),
             kstack_top,
             trap_handler as usize,
         );
+        trap_cx.x[4] = tp;
+        trap_cx.x[10] = 0; // argc
+        trap_cx.x[11] = argv_base; // argv
+        trap_cx.x[12] = envp_base; // envp
 
         // 将主线程加入进程的任务列表
         let mut process_inner = process.inner_exclusive_access();
@@ -119,14 +252,25 @@ impl ProcessControlBlock {
         process
     }
 
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+    /// 子进程只复制调用fork的线程，它在子进程中成为0号线程，内存不足时返回None
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Self>> {
+        let task = current_task().unwrap();
         let mut parent_inner = self.inner_exclusive_access();
 
-        // 只支持单线程
-        assert_eq!(parent_inner.thread_count(), 1);
-
         // 创建新进程
-        let new_memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
+        let mut new_memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)?;
+        // 其他线程在子进程中不存在，去掉它们的用户栈和TrapContext
+        // 0号线程的TrapContext留给子进程的主线程用
+        for other in parent_inner.tasks.iter().flatten() {
+            if let Some(res) = other.inner_exclusive_access().res.as_ref() {
+                if !Arc::ptr_eq(other, &task) {
+                    res.remove_ustack(&mut new_memory_set);
+                }
+                if res.tid != 0 {
+                    res.remove_trap_cx(&mut new_memory_set);
+                }
+            }
+        }
         let new_pid_handle = pid_alloc();
 
         let child = Arc::new(Self {
@@ -138,24 +282,29 @@ impl ProcessControlBlock {
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: 0,
+                    // 堆区域已经在from_existed_user中复制过了
+                    heap_bottom: parent_inner.heap_bottom,
+                    program_brk: parent_inner.program_brk,
+                    stack_perm: parent_inner.stack_perm,
+                    tls: parent_inner.tls.clone(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    signals: parent_inner.signals.fork(),
                 })
             },
         });
 
         parent_inner.children.push(Arc::clone(&child));
 
-        let ustack_base = parent_inner
-            .get_task(0)
+        let ustack_base = task
             .inner_exclusive_access()
             .res
             .as_ref()
             .unwrap()
-            .ustack_base();
+            .ustack_base_as_main();
         let child_main_task = Arc::new(TaskControlBlock::new(
             Arc::clone(&child),
             ustack_base,
@@ -167,82 +316,108 @@ impl ProcessControlBlock {
             .tasks
             .push(Some(Arc::clone(&child_main_task)));
 
-        // modify kstack_top in trap_cx of this thread
+        // 复制调用fork的线程的TrapContext，再修改其中的kstack_top
+        // 在信号处理函数中fork时，子进程同样要能从处理函数返回
         {
-            let inner = child_main_task.inner_exclusive_access();
-            // 在MemorySet::from_existed_user()中已经将父进程的数据复制了一份，
-            // 所以这里的new_trap_cx_ppn是已经复制了父进程的了，跟new()中的空数据不一样
+            let mut inner = child_main_task.inner_exclusive_access();
+            let task_inner = task.inner_exclusive_access();
             let trap_cx = inner.get_trap_cx();
+            *trap_cx = *task_inner.get_trap_cx();
             trap_cx.kernel_sp = child_main_task.kstack.get_top();
+            inner.handling_signal = task_inner.handling_signal;
+            inner.trap_cx_backup = task_inner.trap_cx_backup;
         }
 
         insert_into_pid2process(child.getpid(), Arc::clone(&child));
 
         // add this thread to scheduler
         add_task(child_main_task);
-        child
+        Some(child)
     }
 
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
-        assert_eq!(self.inner_exclusive_access().thread_count(), 1);
-
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
-        let new_token = memory_set.token();
-
-        // 更换地址空间
-        self.inner_exclusive_access().memory_set = memory_set;
+    /// elf不合法时返回错误，原来的地址空间保持不变
+    pub fn exec(
+        self: &Arc<Self>,
+        elf_data: &[u8],
+        args: Vec<String>,
+        envs: Vec<String>,
+    ) -> Result<(), ElfError> {
+        let (memory_set, info) = MemorySet::from_elf(elf_data)?;
+        let auxv = info.auxv();
+
+        // 其他线程都要结束，它们的用户栈和TrapContext必须在更换地址空间之前释放
+        let task = current_task().unwrap();
+        self.kill_other_threads(&task);
+
+        // 更换地址空间，堆也随之重置
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        inner.heap_bottom = info.heap_bottom;
+        inner.program_brk = info.heap_bottom;
+        inner.stack_perm = info.stack_perm;
+        inner.tls = info.tls;
+        inner.signals.exec();
+        // 锁、信号量和条件变量属于原来的程序
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+        // 调用exec的线程成为新程序的0号线程
+        inner.task_res_allocator = RecycleAllocator::new();
+        let tid = inner.alloc_tid();
+        inner.tasks = vec![Some(Arc::clone(&task))];
+        drop(inner);
 
         // 因为地址空间变化，需要重新为主线程分配资源
-        let task = self.inner_exclusive_access().get_task(0);
         let mut task_inner = task.inner_exclusive_access();
-        task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
-        task_inner.res.as_mut().unwrap().alloc_user_res();
-        task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
-
-        // 将参数压入栈中
-        // 假如有2个参数
-        // 用户栈的布局如下
-        // | argc | &argv[0] | &argv[1] | argv[0] | argv[1] |
-        let mut user_sp = task_inner.res.as_ref().unwrap().ustack_top();
-
-        // 为argc和argv分配空间
-        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
-        let argv_base = user_sp;
-        let mut argv: Vec<_> = (0..=args.len())
-            .map(|i| {
-                translate_ref_mut(
-                    new_token,
-                    (argv_base + i * core::mem::size_of::<usize>()) as *mut usize,
-                )
-            })
-            .collect();
-
-        for i in 0..args.len() {
-            user_sp -= args[i].len() + 1;
-            *argv[i] = user_sp;
-            // argv后面的才是数据
-            let mut p = user_sp;
-            for c in args[i].as_bytes() {
-                *translate_ref_mut(new_token, p as *mut u8) = *c;
-                p += 1;
-            }
-            *translate_ref_mut(new_token, p as *mut u8) = 0;
-        }
-
-        // 保证user_sp按8B对齐(K210规定)
-        user_sp -= user_sp % core::mem::size_of::<usize>();
+        let res = task_inner.res.as_mut().unwrap();
+        res.tid = tid;
+        res.ustack_base = info.ustack_base;
+        res.alloc_user_res();
+        task_inner.trap_cx_ppn = task_inner.res.as_ref().unwrap().trap_cx_ppn();
+        task_inner.handling_signal = None;
+        task_inner.trap_cx_backup = None;
+
+        // 将参数和环境变量压入栈中
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        let mut inner = self.inner_exclusive_access();
+        let (user_sp, tp) = inner.push_tls(ustack_top);
+        let (user_sp, argv_base, envp_base) = inner.push_args(user_sp, &args, &envs, &auxv);
+        drop(inner);
 
         // 修改TrapContext
         let mut trap_cx = TrapContext::app_init_context(
-            entry_point,
+            info.entry_point,
             user_sp,
             KERNEL_SPACE.exclusive_access().token(),
             task.kstack.get_top(),
             trap_handler as usize,
         );
+        trap_cx.x[4] = tp;
         trap_cx.x[10] = args.len(); // argc
         trap_cx.x[11] = argv_base; // argv
+        trap_cx.x[12] = envp_base; // envp
         *task_inner.get_trap_cx() = trap_cx;
+        Ok(())
+    }
+
+    /// 结束除了task以外的所有线程，释放它们的用户栈和TrapContext
+    fn kill_other_threads(&self, task: &Arc<TaskControlBlock>) {
+        let inner = self.inner_exclusive_access();
+        let mut user_res: Vec<TaskUserRes> = Vec::new();
+        for other in inner.tasks.iter().flatten() {
+            if Arc::ptr_eq(other, task) {
+                continue;
+            }
+            // 线程可能在就绪队列中，也可能在等待定时器
+            remove_task(Arc::clone(other));
+            remove_timer(other);
+            if let Some(res) = other.inner_exclusive_access().res.take() {
+                user_res.push(res);
+            }
+        }
+        // TaskUserRes的Drop需要访问process inner，先释放借用
+        drop(inner);
+        user_res.clear();
     }
 
     pub fn getpid(&self) -> usize {
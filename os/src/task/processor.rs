@@ -1,4 +1,6 @@
-use crate::{mm::VirtAddr, println, sbi::shutdown, sync::UPSafeCell, trap::TrapContext};
+use crate::{
+    mm::VirtAddr, println, sbi::shutdown, sync::UPSafeCell, timer::remove_timer, trap::TrapContext,
+};
 use alloc::{sync::Arc, vec::Vec};
 use lazy_static::lazy_static;
 
@@ -6,6 +8,7 @@ use super::{
     id::TaskUserRes,
     manager::{add_task, fetch_task, remove_from_pid2process, remove_task},
     process::ProcessControlBlock,
+    signal::SignalFlags,
     switch::__switch,
     task::{TaskControlBlock, TaskStatus},
     TaskContext, INITPROC,
@@ -120,6 +123,18 @@ pub fn run_tasks() {
 }
 
 pub fn block_current_and_run_next() {
+    // 进程已经要被信号结束时不再阻塞，让线程尽快在返回用户态之前退出
+    let handling = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .handling_signal;
+    if current_process()
+        .inner_exclusive_access()
+        .signals
+        .fatal_pending(handling)
+    {
+        return;
+    }
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
@@ -177,6 +192,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         let mut process_inner = process.inner_exclusive_access();
         process_inner.is_zombie = true;
         process_inner.exit_code = exit_code;
+        // 通知父进程，SIGCHLD默认被忽略
+        if let Some(parent) = process_inner.parent.as_ref().and_then(|p| p.upgrade()) {
+            parent
+                .inner_exclusive_access()
+                .signals
+                .add(SignalFlags::SIGCHLD);
+        }
         // 把当前进程的子进程都设置为initproc的子进程
         {
             let mut initproc_inner = INITPROC.inner_exclusive_access();
@@ -192,6 +214,8 @@ pub fn exit_current_and_run_next(exit_code: i32) {
             let task = task.as_ref().unwrap();
 
             remove_task(Arc::clone(&task));
+            // 正在睡眠的线程也不能再被定时器唤醒
+            remove_timer(task);
             let mut task_inner = task.inner_exclusive_access();
 
             // TaskUserRes的Drop trait需要访问process inner
@@ -0,0 +1,366 @@
+//! 进程信号
+//!
+//! 信号发给进程，每个进程有待处理（pending）和屏蔽（blocked）两个集合，
+//! 在返回用户态之前（trap_return）检查并处理：执行默认动作，
+//! 或者保存TrapContext后跳到用户注册的handler，handler结束时调用sigreturn恢复。
+//! 用户程序的缺页、非法指令等硬件异常也转换成SIGSEGV/SIGILL/SIGBUS交给它自己处理
+
+use alloc::{sync::Arc, vec::Vec};
+use bitflags::bitflags;
+
+use super::process::ProcessControlBlock;
+use super::task::{TaskControlBlock, TaskStatus};
+use super::{block_current_and_run_next, current_process, current_task};
+use super::{exit_current_and_run_next, wakeup_task};
+use crate::println;
+
+pub const MAX_SIG: usize = 31;
+/// 执行默认动作
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// 第signum位表示编号为signum的信号，编号和Linux一致
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+/// 不能被屏蔽、忽略或者捕获的信号
+const UNCATCHABLE: SignalFlags = SignalFlags::SIGKILL.union(SignalFlags::SIGSTOP);
+/// 让进程暂停的信号
+const STOP_SIGNALS: SignalFlags = SignalFlags::SIGSTOP
+    .union(SignalFlags::SIGTSTP)
+    .union(SignalFlags::SIGTTIN)
+    .union(SignalFlags::SIGTTOU);
+
+/// 信号的默认动作
+#[derive(PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+impl SignalFlags {
+    /// signum对应的信号，不合法时返回None
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            return None;
+        }
+        Self::from_bits(1 << signum)
+    }
+
+    pub fn default_action(self) -> DefaultAction {
+        if STOP_SIGNALS.contains(self) {
+            DefaultAction::Stop
+        } else if self == Self::SIGCONT {
+            DefaultAction::Continue
+        } else if (Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH).contains(self) {
+            DefaultAction::Ignore
+        } else {
+            DefaultAction::Terminate
+        }
+    }
+
+    pub fn catchable(self) -> bool {
+        !UNCATCHABLE.contains(self)
+    }
+}
+
+/// 用户通过sigaction注册的信号处理方式
+/// handler为SIG_DFL、SIG_IGN或者处理函数的地址，mask为handler执行期间额外屏蔽的信号
+/// restorer是handler返回的地址，由它调用sigreturn，和Linux的sa_restorer一样
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub mask: SignalFlags,
+    pub restorer: usize,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+/// 进程的信号状态
+#[derive(Clone)]
+pub struct SignalState {
+    /// 待处理的信号
+    pub pending: SignalFlags,
+    /// 被屏蔽的信号
+    pub blocked: SignalFlags,
+    pub actions: [SignalAction; MAX_SIG + 1],
+    /// 被信号暂停，收到SIGCONT或SIGKILL之前不会回到用户态
+    pub frozen: bool,
+    /// 因为暂停而阻塞的线程，收到SIGCONT或SIGKILL时被唤醒
+    stopped: Vec<Arc<TaskControlBlock>>,
+    /// 硬件异常产生的信号对应的出错地址，作为handler的第二个参数
+    fault_addrs: [usize; MAX_SIG + 1],
+    /// 结束进程的信号，进程的每个线程回到用户态之前都会退出
+    pub killed: Option<usize>,
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        Self {
+            pending: SignalFlags::empty(),
+            blocked: SignalFlags::empty(),
+            actions: [SignalAction::default(); MAX_SIG + 1],
+            frozen: false,
+            stopped: Vec::new(),
+            fault_addrs: [0; MAX_SIG + 1],
+            killed: None,
+        }
+    }
+
+    /// fork出的子进程继承屏蔽字和处理方式，但没有待处理的信号
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: SignalFlags::empty(),
+            frozen: false,
+            stopped: Vec::new(),
+            fault_addrs: [0; MAX_SIG + 1],
+            killed: None,
+            ..self.clone()
+        }
+    }
+
+    /// exec之后原来的handler已经不存在了，恢复成默认动作，被忽略的信号仍然忽略
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+    }
+
+    /// 产生一个信号，SIGCONT和暂停的信号在产生时就互相抵消
+    /// SIGCONT和SIGKILL唤醒暂停的线程，让它们继续运行或者退出
+    pub fn add(&mut self, signal: SignalFlags) {
+        if signal == SignalFlags::SIGCONT {
+            self.pending.remove(STOP_SIGNALS);
+            self.frozen = false;
+        } else if STOP_SIGNALS.contains(signal) {
+            self.pending.remove(SignalFlags::SIGCONT);
+        }
+        self.pending.insert(signal);
+        if signal == SignalFlags::SIGCONT || signal == SignalFlags::SIGKILL {
+            for task in self.stopped.drain(..) {
+                wakeup_task(task);
+            }
+        }
+    }
+
+    /// 硬件异常产生的信号，addr为出错的地址，handling为出错的线程正在执行handler的信号
+    /// 用户程序注册了handler并且现在能执行它时返回true，否则进程只能被结束，返回false
+    pub fn add_fault(&mut self, signum: usize, addr: usize, handling: Option<usize>) -> bool {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        // 忽略或者屏蔽了异常信号时，返回用户态后会再次触发同一个异常
+        // 正在执行handler时又触发异常，也没有办法再嵌套一个handler
+        if self.actions[signum].handler <= SIG_IGN
+            || self.blocked(handling).contains(signal)
+            || handling.is_some()
+        {
+            self.killed = Some(signum);
+            return false;
+        }
+        self.pending.insert(signal);
+        self.fault_addrs[signum] = addr;
+        true
+    }
+
+    /// 现在被屏蔽的信号，handling为当前线程正在执行handler的信号
+    fn blocked(&self, handling: Option<usize>) -> SignalFlags {
+        let mut blocked = self.blocked;
+        if let Some(signum) = handling {
+            blocked |= self.actions[signum].mask | SignalFlags::from_signum(signum).unwrap();
+        }
+        blocked
+    }
+
+    /// 会结束进程的待处理信号：SIGKILL，或者没有被屏蔽、执行默认动作的终止信号
+    /// 暂停时只有SIGKILL能让进程退出
+    fn fatal(&self, handling: Option<usize>) -> Option<usize> {
+        if self.killed.is_some() {
+            return self.killed;
+        }
+        let blocked = self.blocked(handling);
+        (1..=MAX_SIG).find(|&signum| {
+            let signal = SignalFlags::from_signum(signum).unwrap();
+            self.pending.contains(signal)
+                && (signal == SignalFlags::SIGKILL
+                    || (!self.frozen
+                        && !blocked.contains(signal)
+                        && self.actions[signum].handler == SIG_DFL
+                        && signal.default_action() == DefaultAction::Terminate))
+        })
+    }
+
+    /// 进程是否要被信号结束，这时阻塞的线程要被唤醒，也不能再阻塞
+    /// handling为线程正在执行handler的信号
+    pub fn fatal_pending(&self, handling: Option<usize>) -> bool {
+        self.fatal(handling).is_some()
+    }
+
+    /// 当前线程下一个要处理的信号，会结束进程的信号优先处理
+    /// 被信号唤醒的线程没有完成阻塞前的操作，不能再回到用户态执行handler
+    fn next(&self, handling: Option<usize>) -> Option<usize> {
+        if let Some(signum) = self.fatal(handling) {
+            return Some(signum);
+        }
+        let blocked = self.blocked(handling);
+        (1..=MAX_SIG).find(|&signum| {
+            let signal = SignalFlags::from_signum(signum).unwrap();
+            if !self.pending.contains(signal) {
+                return false;
+            }
+            if !signal.catchable() {
+                return true;
+            }
+            // 暂停时只有SIGKILL能让进程退出，SIGCONT在产生时就解除了暂停
+            if self.frozen || blocked.contains(signal) {
+                return false;
+            }
+            // 同一时间只能执行一个用户handler，其他要执行handler的信号等sigreturn之后再处理
+            handling.is_none() || self.actions[signum].handler <= SIG_IGN
+        })
+    }
+}
+
+/// 返回用户态之前处理当前进程的信号
+pub fn handle_signals() {
+    let task = current_task().unwrap();
+    loop {
+        let process = current_process();
+        let mut inner = process.inner_exclusive_access();
+        // handler的执行状态属于线程，一个线程在handler中退出不会影响其他线程
+        let handling = task.inner_exclusive_access().handling_signal;
+        let signum = match inner.signals.next(handling) {
+            Some(signum) => signum,
+            None if inner.signals.frozen => {
+                // 暂停的线程阻塞起来，收到SIGCONT或SIGKILL时在SignalState::add中被唤醒
+                inner.signals.stopped.push(Arc::clone(&task));
+                drop(inner);
+                drop(process);
+                block_current_and_run_next();
+                continue;
+            }
+            None => return,
+        };
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        let action = inner.signals.actions[signum];
+        if inner.signals.killed.is_some() || !signal.catchable() || action.handler == SIG_DFL {
+            match signal.default_action() {
+                _ if inner.signals.killed.is_some() => {
+                    drop(inner);
+                    drop(process);
+                    drop(task);
+                    terminate(signum);
+                }
+                DefaultAction::Terminate => {
+                    inner.signals.killed = Some(signum);
+                    drop(inner);
+                    drop(process);
+                    drop(task);
+                    terminate(signum);
+                }
+                DefaultAction::Stop => inner.signals.frozen = true,
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            }
+            inner.signals.pending.remove(signal);
+            continue;
+        }
+        inner.signals.pending.remove(signal);
+        let fault_addr = core::mem::take(&mut inner.signals.fault_addrs[signum]);
+        if action.handler == SIG_IGN {
+            continue;
+        }
+        // 保存现场，返回用户态时从handler开始执行，a0为信号编号，a1为硬件异常的出错地址，
+        // ra为restorer，handler返回时由它调用sigreturn
+        let mut task_inner = task.inner_exclusive_access();
+        let trap_cx = task_inner.get_trap_cx();
+        task_inner.trap_cx_backup = Some(*trap_cx);
+        task_inner.handling_signal = Some(signum);
+        trap_cx.sepc = action.handler;
+        trap_cx.x[1] = action.restorer;
+        trap_cx.x[10] = signum;
+        trap_cx.x[11] = fault_addr;
+        return;
+    }
+}
+
+/// 给进程发送信号
+/// 信号会结束进程时，阻塞在锁、信号量、条件变量或者sleep中的线程也要被唤醒，
+/// 它们在返回用户态之前退出
+pub fn send_signal(process: &Arc<ProcessControlBlock>, signal: SignalFlags) {
+    let mut inner = process.inner_exclusive_access();
+    inner.signals.add(signal);
+    let mut woken = Vec::new();
+    for task in inner.tasks.iter().flatten() {
+        let task_inner = task.inner_exclusive_access();
+        if task_inner.task_status == TaskStatus::Blocked
+            && inner.signals.fatal_pending(task_inner.handling_signal)
+        {
+            woken.push(Arc::clone(task));
+        }
+    }
+    drop(inner);
+    for task in woken {
+        wakeup_task(task);
+    }
+}
+
+/// 被信号signum结束，进程的killed已经设置好了
+/// 主线程退出时整个进程结束，其他线程直接退出，主线程回到用户态之前也会退出
+fn terminate(signum: usize) -> ! {
+    let task = current_task().unwrap();
+    if task.gettid() == 0 {
+        println!(
+            "[kernel] Process {} killed by signal {}",
+            task.getpid(),
+            signum
+        );
+    }
+    drop(task);
+    exit_current_and_run_next(-(signum as i32));
+    unreachable!();
+}
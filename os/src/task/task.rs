@@ -45,6 +45,10 @@ pub struct TaskControlBlockInner {
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
     pub exit_code: Option<i32>,
+    /// 正在执行用户handler的信号
+    pub handling_signal: Option<usize>,
+    /// 执行信号handler之前保存的TrapContext，sigreturn时恢复
+    pub trap_cx_backup: Option<TrapContext>,
 }
 
 impl TaskControlBlockInner {
@@ -72,6 +76,8 @@ impl TaskControlBlock {
                 task_cx: TaskContext::goto_trap_return(kstack_top),
                 task_status: TaskStatus::Ready,
                 exit_code: None,
+                handling_signal: None,
+                trap_cx_backup: None,
             }),
         }
     }
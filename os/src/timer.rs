@@ -69,6 +69,12 @@ pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
 }
 
 
+/// 去掉线程task的定时器，线程被结束之后不能再被唤醒
+pub fn remove_timer(task: &Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.retain(|timer| !Arc::ptr_eq(&timer.task, task));
+}
+
 /// 唤醒已经超时的线程
 pub fn check_timer() {
     let current_ms = get_time_ms();
@@ -3,11 +3,13 @@ pub use context::TrapContext;
 use riscv::register::sie;
 
 use crate::config::{TRAMPOLINE_ADDRESS, TRAP_CONTEXT_ADDRESS};
+use crate::mm::MapPermission;
 use crate::task::{
-    current_task, current_trap_cx, current_user_token, suspend_current_and_run_next,
+    current_process, current_task, current_trap_cx, current_user_token, handle_signals,
+    reclaim_frames, suspend_current_and_run_next, SignalFlags,
 };
 use crate::timer::check_timer;
-use crate::{task::exit_current_and_run_next, timer::set_next_trigger};
+use crate::timer::set_next_trigger;
 // use crate::batch::run_next_app;
 use crate::println;
 use crate::syscall::syscall;
@@ -57,16 +59,55 @@ pub fn trap_handler() -> ! {
             // cx.x[10]为a0，保存返回值
             cx.x[10] = result;
         }
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+            if handle_user_page_fault(scause.cause(), stval) =>
+        {
+            // 懒分配、写时复制或换出的页面已经处理好了，回到用户态重新执行这条指令即可
+        }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
-            println!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, current_trap_cx().sepc);
-            exit_current_and_run_next(-2);
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            // 交给用户程序的SIGSEGV handler，没有handler时进程在返回用户态之前被结束
+            if !add_fault_signal(SignalFlags::SIGSEGV, stval) {
+                let stack_overflow = current_process()
+                    .inner_exclusive_access()
+                    .memory_set
+                    .is_stack_overflow(stval.into());
+                if stack_overflow {
+                    println!("[kernel] Stack overflow in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, current_trap_cx().sepc);
+                } else {
+                    println!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, current_trap_cx().sepc);
+                }
+            }
+        }
+        Trap::Exception(Exception::InstructionMisaligned)
+        | Trap::Exception(Exception::StoreMisaligned) => {
+            if !add_fault_signal(SignalFlags::SIGBUS, stval) {
+                println!("[kernel] Misaligned access in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, current_trap_cx().sepc);
+            }
+        }
+        // riscv库没有区分读数据不对齐（scause为4），它和其他未知异常一样是Exception::Unknown
+        Trap::Exception(Exception::Unknown) if scause.bits() == 4 => {
+            if !add_fault_signal(SignalFlags::SIGBUS, stval) {
+                println!("[kernel] Misaligned access in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, current_trap_cx().sepc);
+            }
+        }
+        Trap::Exception(Exception::Breakpoint) => {
+            // 出错的地址就是ebreak指令的地址
+            if !add_fault_signal(SignalFlags::SIGTRAP, current_trap_cx().sepc) {
+                println!("[kernel] Breakpoint in application, kernel killed it.");
+            }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, kernel killed it.");
-            exit_current_and_run_next(-3);
+            // 出错的地址就是这条指令的地址
+            if !add_fault_signal(SignalFlags::SIGILL, current_trap_cx().sepc) {
+                println!("[kernel] IllegalInstruction in application, kernel killed it.");
+            }
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             // 定时器中断
@@ -85,6 +126,32 @@ pub fn trap_handler() -> ! {
     trap_return();
 }
 
+/// 用户程序的硬件异常转换成信号，返回用户程序能否用自己的handler处理
+fn add_fault_signal(signal: SignalFlags, addr: usize) -> bool {
+    let signum = signal.bits().trailing_zeros() as usize;
+    let handling = current_task().unwrap().inner_exclusive_access().handling_signal;
+    current_process()
+        .inner_exclusive_access()
+        .signals
+        .add_fault(signum, addr, handling)
+}
+
+/// 尝试处理用户程序的缺页异常，返回是否处理成功
+fn handle_user_page_fault(cause: Trap, stval: usize) -> bool {
+    // 根据异常类型得到这次访问需要的权限
+    let access = match cause {
+        Trap::Exception(Exception::StorePageFault) => MapPermission::W,
+        Trap::Exception(Exception::InstructionPageFault) => MapPermission::X,
+        _ => MapPermission::R,
+    };
+    // 处理缺页可能要分配物理页帧，空闲页帧不多时先换出一些页面
+    reclaim_frames();
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .handle_page_fault(stval.into(), access)
+}
+
 fn set_kernel_trap_entry() {
     unsafe {
         // stvec寄存器保存中断处理函数的地址，
@@ -117,6 +184,8 @@ fn trap_from_kernel() {
 
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // 返回用户态之前处理信号，可能会跳到信号handler，或者直接结束进程
+    handle_signals();
     // 让应用在U->S时，可以跳转到__alltraps
     set_user_trap_entry();
     let trap_cx_ptr = TRAP_CONTEXT_ADDRESS;
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{args, env, exec, execve, getenv, waitpid};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let argv = args();
+    if argv.len() == 1 {
+        // 带上参数和环境变量重新执行自己
+        assert_eq!(argv[0], "argv");
+        let args = ["argv\0".as_ptr(), "hello\0".as_ptr(), "world\0".as_ptr()];
+        let envs = ["USER=zos\0".as_ptr(), "HOME=/\0".as_ptr()];
+
+        // 参数太长时exec失败，程序还在
+        let mut long = String::from("x");
+        long.extend((0..4096).map(|_| 'x'));
+        long.push('\0');
+        assert_eq!(exec("argv\0", &[long.as_ptr()]), -7);
+
+        execve("argv\0", &args, &envs);
+        unreachable!();
+    }
+    if argv.len() == 3 {
+        assert_eq!(argv, ["argv", "hello", "world"]);
+        assert_eq!(env(), ["USER=zos", "HOME=/"]);
+        assert_eq!(getenv("HOME"), Some("/"));
+        assert_eq!(getenv("PATH"), None);
+        // exec时继承环境变量
+        let pid = user_lib::fork();
+        if pid == 0 {
+            exec("argv\0", &["argv\0".as_ptr(), "child\0".as_ptr()]);
+            unreachable!();
+        }
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+        println!("argv passed!");
+        return 0;
+    }
+    assert_eq!(argv, ["argv", "child"]);
+    assert_eq!(getenv("USER"), Some("zos"));
+    0
+}
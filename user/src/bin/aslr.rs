@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, exit, fork, waitpid};
+
+const RUNS: usize = 8;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut layouts = [0i32; RUNS];
+    for layout in layouts.iter_mut() {
+        let pid = fork();
+        if pid == 0 {
+            exec("aslr_probe\0", &[]);
+            exit(-1);
+        }
+        assert_eq!(waitpid(pid as usize, layout), pid);
+        assert_ne!(*layout, -1);
+    }
+    let distinct = (0..RUNS)
+        .filter(|&i| !layouts[..i].contains(&layouts[i]))
+        .count();
+    // 关闭ASLR时每次的布局都一样
+    println!("{} distinct layouts in {} runs", distinct, RUNS);
+    if distinct > 1 {
+        println!("aslr pass.");
+    } else {
+        println!("aslr is off.");
+    }
+    0
+}
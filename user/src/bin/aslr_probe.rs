@@ -0,0 +1,17 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::sbrk;
+
+const PAGE_SIZE: usize = 0x1000;
+
+// 用栈和堆所在的页号作为退出码，由aslr测试比较不同进程的地址布局
+#[no_mangle]
+pub fn main() -> i32 {
+    let local = 0u8;
+    let stack_page = core::hint::black_box(&local) as *const u8 as usize / PAGE_SIZE;
+    let heap_page = sbrk(0) as usize / PAGE_SIZE;
+    ((stack_page ^ (heap_page << 16)) & 0x7fff_ffff) as i32
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getauxval, AT_ENTRY, AT_PAGESZ, AT_PHDR, AT_PHNUM, AT_RANDOM};
+
+/// 一个Elf64_Phdr的大小
+const PHDR_SIZE: usize = 56;
+const PT_LOAD: u32 = 1;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(getauxval(AT_PAGESZ), Some(0x1000));
+    assert_eq!(
+        getauxval(AT_ENTRY),
+        Some(user_lib::_start as *const () as usize)
+    );
+    assert_eq!(getauxval(12345), None);
+
+    // 通过AT_PHDR读自己的程序头表
+    let phdr = getauxval(AT_PHDR).unwrap();
+    let phnum = getauxval(AT_PHNUM).unwrap();
+    assert!(phdr != 0 && phnum > 0);
+    let loads = (0..phnum)
+        .filter(|i| unsafe { *((phdr + i * PHDR_SIZE) as *const u32) } == PT_LOAD)
+        .count();
+    assert!(loads > 0);
+    println!(
+        "{} program headers at {:#x}, {} PT_LOAD",
+        phnum, phdr, loads
+    );
+
+    // 16字节的随机数
+    let random = getauxval(AT_RANDOM).unwrap();
+    let bytes = unsafe { core::slice::from_raw_parts(random as *const u8, 16) };
+    assert!(bytes.iter().any(|&b| b != 0));
+    println!("auxv passed!");
+    0
+}
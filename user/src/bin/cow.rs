@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, wait, waitpid};
+
+const MAGIC: usize = 0x2333;
+static mut DATA: [usize; 1024] = [0; 1024];
+
+fn data() -> &'static mut [usize; 1024] {
+    unsafe { &mut *core::ptr::addr_of_mut!(DATA) }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("Test copy on write start.");
+    data().iter_mut().for_each(|x| *x = MAGIC);
+    let pid = fork();
+    if pid == 0 {
+        // 子进程看到的是fork时父进程的数据
+        assert!(data().iter().all(|x| *x == MAGIC));
+        // 子进程写入不影响父进程
+        data().iter_mut().enumerate().for_each(|(i, x)| *x = i);
+        assert!(data().iter().enumerate().all(|(i, x)| *x == i));
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert!(data().iter().all(|x| *x == MAGIC));
+    println!("child write ok");
+
+    // 父进程先写，子进程仍然看到fork时的数据
+    let pid = fork();
+    if pid == 0 {
+        for _ in 0..10 {
+            user_lib::yield_();
+        }
+        assert!(data().iter().all(|x| *x == MAGIC));
+        exit(0);
+    }
+    data().iter_mut().for_each(|x| *x = 0);
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("parent write ok");
+    println!("cow pass.");
+    0
+}
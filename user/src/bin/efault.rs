@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, meminfo, waitpid, write, MemInfo};
+
+const EFAULT: isize = -14;
+// 没有映射的用户地址
+const UNMAPPED: usize = 0x20_0000_0000;
+// 超出Sv39用户地址空间的地址
+const NON_CANONICAL: usize = 0x8000_0000_0000_1000;
+// 跳板页所在的地址，没有U位
+const TRAMPOLINE: usize = usize::MAX - 0xfff;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    for addr in [UNMAPPED, NON_CANONICAL, TRAMPOLINE] {
+        let buf = unsafe { core::slice::from_raw_parts(addr as *const u8, 16) };
+        assert_eq!(write(1, buf), EFAULT);
+        let info = unsafe { &mut *(addr as *mut MemInfo) };
+        assert_eq!(meminfo(info), EFAULT);
+    }
+    println!("write/meminfo ok");
+
+    let pid = fork();
+    if pid == 0 {
+        exit(7);
+    }
+    // 退出码写不进去时子进程不会被回收，还可以再等待一次
+    let bad = unsafe { &mut *(UNMAPPED as *mut i32) };
+    assert_eq!(waitpid(pid as usize, bad), EFAULT);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    println!("efault pass.");
+    0
+}
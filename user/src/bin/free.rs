@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, MemInfo};
+
+const PAGE_SIZE: usize = 0x1000;
+
+fn kib(pages: usize) -> usize {
+    pages * PAGE_SIZE / 1024
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut info = MemInfo::default();
+    assert_eq!(meminfo(&mut info), 0);
+    println!("{:>8} {:>10} {:>10} {:>10}", "", "total", "used", "free");
+    println!(
+        "{:>8} {:>9}K {:>9}K {:>9}K",
+        "Mem:",
+        kib(info.total_frames),
+        kib(info.total_frames - info.free_frames),
+        kib(info.free_frames)
+    );
+    println!(
+        "{:>8} {:>9}K {:>9}K {:>9}K",
+        "Swap:",
+        kib(info.swap_total_pages),
+        kib(info.swap_used_pages),
+        kib(info.swap_total_pages - info.swap_used_pages)
+    );
+    println!(
+        "{:>8} {:>9}K {:>9}K {:>9}K",
+        "KHeap:",
+        info.kernel_heap_total / 1024,
+        info.kernel_heap_used / 1024,
+        (info.kernel_heap_total - info.kernel_heap_used) / 1024
+    );
+    println!("Zero page saved: {}K", kib(info.zero_page_saved));
+    0
+}
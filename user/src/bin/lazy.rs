@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, munmap, waitpid, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+// 比整个物理内存还大，只有真正访问到的页面才会分配物理页帧
+const BIG_SIZE: usize = 0x4000_0000; // 1G
+const BSS_SIZE: usize = 0x200_0000; // 32M
+
+static mut BSS: [u8; BSS_SIZE] = [0; BSS_SIZE];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("Test lazy allocation start.");
+    let bss = core::ptr::addr_of_mut!(BSS) as usize;
+    for offset in [0, BSS_SIZE / 2, BSS_SIZE - 1] {
+        let p = (bss + offset) as *mut u8;
+        assert_eq!(unsafe { p.read_volatile() }, 0);
+        unsafe { p.write_volatile(0x5a) };
+        assert_eq!(unsafe { p.read_volatile() }, 0x5a);
+    }
+    println!("bss ok");
+
+    let start = mmap(0, BIG_SIZE, PROT_READ | PROT_WRITE);
+    assert!(start > 0);
+    let start = start as usize;
+    for offset in (0..BIG_SIZE).step_by(BIG_SIZE / 16) {
+        let p = (start + offset) as *mut usize;
+        assert_eq!(unsafe { p.read_volatile() }, 0);
+        unsafe { p.write_volatile(offset) };
+    }
+    for offset in (0..BIG_SIZE).step_by(BIG_SIZE / 16) {
+        assert_eq!(
+            unsafe { ((start + offset) as *const usize).read_volatile() },
+            offset
+        );
+    }
+    assert_eq!(munmap(start, BIG_SIZE), 0);
+    println!("mmap ok");
+
+    // 访问不属于任何MapArea的地址，子进程仍然会被杀掉
+    let pid = fork();
+    if pid == 0 {
+        unsafe { ((start + PAGE_SIZE) as *mut u8).write_volatile(1) };
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_ne!(exit_code, 0);
+    println!("lazy pass.");
+    0
+}
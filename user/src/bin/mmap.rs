@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, mprotect, munmap, waitpid, PROT_EXEC, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("Test mmap start.");
+    // 由内核选择地址，映射4页
+    let start = mmap(0, PAGE_SIZE * 4, PROT_READ | PROT_WRITE);
+    assert!(start > 0);
+    let start = start as usize;
+    println!("mmap 4 pages at {:#x}", start);
+    for i in 0..PAGE_SIZE * 4 {
+        unsafe { ((start + i) as *mut u8).write_volatile(i as u8) };
+    }
+    for i in 0..PAGE_SIZE * 4 {
+        assert_eq!(
+            unsafe { ((start + i) as *const u8).read_volatile() },
+            i as u8
+        );
+    }
+    println!("write ok");
+
+    // 非法参数
+    assert_eq!(mmap(start, PAGE_SIZE, PROT_READ), -1); // 重叠
+    assert_eq!(mmap(start + 1, PAGE_SIZE, PROT_READ), -1); // 没有对齐
+    assert_eq!(mmap(0, PAGE_SIZE, 0), -1); // 没有权限
+    assert_eq!(mmap(0, PAGE_SIZE, 0x8), -1); // 多余的位
+    assert_eq!(mmap(0, 0, PROT_READ), -1); // 长度为0
+    assert_eq!(mmap(0x1000, PAGE_SIZE, PROT_READ), -1); // 不在mmap区域内
+
+    // 取消中间一页的映射，原来的区域被拆成两段
+    assert_eq!(munmap(start + PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(munmap(start + PAGE_SIZE, PAGE_SIZE), -1);
+    assert_eq!(munmap(start, PAGE_SIZE * 2), -1);
+    // 空出来的那一页可以重新映射
+    assert_eq!(
+        mmap(
+            start + PAGE_SIZE,
+            PAGE_SIZE,
+            PROT_READ | PROT_WRITE | PROT_EXEC
+        ),
+        (start + PAGE_SIZE) as isize
+    );
+    println!("munmap ok");
+
+    // 把最后一页改成只读，写它的子进程会被杀掉
+    assert_eq!(mprotect(start + PAGE_SIZE * 3, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(
+        unsafe { ((start + PAGE_SIZE * 3) as *const u8).read_volatile() },
+        (PAGE_SIZE * 3) as u8
+    );
+    let pid = fork();
+    if pid == 0 {
+        unsafe { ((start + PAGE_SIZE * 3) as *mut u8).write_volatile(0) };
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_ne!(exit_code, 0);
+    println!("mprotect ok");
+
+    assert_eq!(munmap(start, PAGE_SIZE * 4), 0);
+    println!("mmap pass.");
+    0
+}
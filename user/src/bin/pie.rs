@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+#![feature(thread_local)]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, thread_create, waittid};
+
+// 位置无关的可执行文件被加载到这个地址之上
+const PIE_BASE: usize = 0x4000_0000;
+
+fn one() -> usize {
+    1
+}
+
+fn two() -> usize {
+    2
+}
+
+// 静态数据中的指针要在加载时按R_RISCV_RELATIVE重定位
+static NAMES: [&str; 2] = ["one", "two"];
+static FUNCS: [fn() -> usize; 2] = [one, two];
+
+#[thread_local]
+static mut COUNTER: usize = 5;
+
+fn bump(times: usize) -> usize {
+    for _ in 0..times {
+        unsafe { COUNTER += 1 };
+    }
+    unsafe { COUNTER }
+}
+
+pub fn thread_entry(times: usize) -> ! {
+    // 每个线程都从TLS的初始值开始
+    exit(bump(times) as i32)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert!(main as *const () as usize >= PIE_BASE);
+    assert_eq!(NAMES[0], "one");
+    assert_eq!(NAMES[1], "two");
+    assert_eq!(FUNCS[0]() + FUNCS[1](), 3);
+    println!("relocation ok");
+
+    let tids = [
+        thread_create(thread_entry as *const () as usize, 10),
+        thread_create(thread_entry as *const () as usize, 20),
+    ];
+    assert_eq!(bump(1), 6);
+    assert_eq!(waittid(tids[0] as usize), 15);
+    assert_eq!(waittid(tids[1] as usize), 25);
+    assert_eq!(bump(1), 7);
+    println!("pie pass.");
+    0
+}
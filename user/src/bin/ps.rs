@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{procinfo, ProcInfo};
+
+const PAGE_SIZE: usize = 0x1000;
+const MAX_PROCS: usize = 64;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [ProcInfo::default(); MAX_PROCS];
+    let total = procinfo(&mut buf) as usize;
+    println!(
+        "{:>5} {:>5} {:>7} {:>10} {:>10}",
+        "PID", "PPID", "THREADS", "RSS", "SWAP"
+    );
+    for info in buf.iter().take(total.min(MAX_PROCS)) {
+        println!(
+            "{:>5} {:>5} {:>7} {:>9}K {:>9}K",
+            info.pid,
+            info.ppid,
+            info.threads,
+            info.resident_pages * PAGE_SIZE / 1024,
+            info.swapped_pages * PAGE_SIZE / 1024
+        );
+    }
+    if total > MAX_PROCS {
+        println!("... and {} more processes", total - MAX_PROCS);
+    }
+    0
+}
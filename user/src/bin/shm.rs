@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mprotect, munmap, shmat, shmctl, shmdt, shmget, waitpid, IPC_PRIVATE, IPC_RMID,
+    PROT_READ,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const KEY: usize = 0x2333;
+const MAGIC: usize = 0xdead_beef;
+
+fn slot(base: usize, i: usize) -> *mut usize {
+    (base + i * PAGE_SIZE) as *mut usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("Test shared memory start.");
+    let id = shmget(KEY, PAGE_SIZE * 2);
+    assert!(id >= 0);
+    let id = id as usize;
+    // 同一个key得到同一个共享内存段
+    assert_eq!(shmget(KEY, PAGE_SIZE), id as isize);
+    assert_eq!(shmget(KEY, PAGE_SIZE * 3), -1);
+    assert_ne!(shmget(IPC_PRIVATE, PAGE_SIZE), id as isize);
+
+    let base = shmat(id, 0);
+    assert!(base > 0);
+    let base = base as usize;
+    unsafe { slot(base, 0).write_volatile(MAGIC) };
+
+    let pid = fork();
+    if pid == 0 {
+        // 子进程再映射一次，两个地址看到的是同一块内存
+        let other = shmat(shmget(KEY, PAGE_SIZE) as usize, 0);
+        assert!(other > 0);
+        let other = other as usize;
+        assert_ne!(other, base);
+        assert_eq!(unsafe { slot(other, 0).read_volatile() }, MAGIC);
+        unsafe { slot(other, 1).write_volatile(1) };
+        // fork继承下来的映射也是共享的，不会写时复制
+        unsafe { slot(base, 0).write_volatile(MAGIC + 1) };
+        assert_eq!(shmdt(other), 0);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { slot(base, 0).read_volatile() }, MAGIC + 1);
+    assert_eq!(unsafe { slot(base, 1).read_volatile() }, 1);
+    println!("share between processes ok");
+
+    // 删除后id不能再映射，但已有的映射仍然有效
+    assert_eq!(shmctl(id, IPC_RMID), 0);
+    assert_eq!(shmctl(id, IPC_RMID), -1);
+    assert_eq!(shmat(id, 0), -1);
+    assert_eq!(unsafe { slot(base, 0).read_volatile() }, MAGIC + 1);
+    assert_eq!(shmdt(base + PAGE_SIZE), -1);
+    // 共享内存映射不能只取消或修改其中的一部分
+    assert_eq!(munmap(base, PAGE_SIZE), -1);
+    assert_eq!(mprotect(base + PAGE_SIZE, PAGE_SIZE, PROT_READ), -1);
+    assert_eq!(shmdt(base), 0);
+    assert_eq!(shmdt(base), -1);
+    println!("shm pass.");
+    0
+}
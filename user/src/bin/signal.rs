@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, semaphore_create, semaphore_down, sigaction, sigmask, sigprocmask,
+    sleep, waitpid, SignalAction, SIGCONT, SIGKILL, SIGSTOP, SIGTERM, SIGUSR1, SIGUSR2, SIG_IGN,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+// handler返回到sigaction填入的restorer，由它调用sigreturn
+extern "C" fn handler(signum: i32) {
+    assert_eq!(signum, SIGUSR1);
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn spin() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+
+    // 用户handler
+    let action = SignalAction {
+        handler: handler as *const () as usize,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    let mut old = SignalAction::default();
+    assert_eq!(sigaction(SIGUSR1, None, Some(&mut old)), 0);
+    assert_eq!(old.handler, action.handler);
+    assert_ne!(old.restorer, 0);
+    println!("handler ok");
+
+    // 屏蔽的信号等解除屏蔽后再处理
+    assert_eq!(sigprocmask(sigmask(SIGUSR1)), 0);
+    kill(pid, SIGUSR1);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    assert_eq!(sigprocmask(0), sigmask(SIGUSR1) as isize);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    println!("mask ok");
+
+    // 忽略的信号
+    let ignore = SignalAction {
+        handler: SIG_IGN,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGUSR2, Some(&ignore), None), 0);
+    kill(pid, SIGUSR2);
+    // SIGKILL和SIGSTOP的处理方式不能修改
+    assert_eq!(sigaction(SIGKILL, Some(&ignore), None), -1);
+    assert_eq!(sigaction(SIGSTOP, Some(&ignore), None), -1);
+    println!("ignore ok");
+
+    // 默认动作：结束进程
+    let mut exit_code: i32 = 0;
+    let child = fork();
+    if child == 0 {
+        spin();
+    }
+    assert_eq!(kill(child as usize, SIGTERM), 0);
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -SIGTERM);
+
+    // 阻塞在信号量上的进程也会被唤醒并结束
+    let child = fork();
+    if child == 0 {
+        let sem_id = semaphore_create(0) as usize;
+        semaphore_down(sem_id);
+        unreachable!();
+    }
+    sleep(10);
+    assert_eq!(kill(child as usize, SIGTERM), 0);
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -SIGTERM);
+
+    // 暂停和继续，暂停的进程仍然可以被SIGKILL结束
+    let child = fork();
+    if child == 0 {
+        spin();
+    }
+    assert_eq!(kill(child as usize, SIGSTOP), 0);
+    sleep(10);
+    assert_eq!(kill(child as usize, SIGCONT), 0);
+    sleep(10);
+    assert_eq!(kill(child as usize, SIGSTOP), 0);
+    assert_eq!(kill(child as usize, SIGKILL), 0);
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -SIGKILL);
+    assert_eq!(kill(child as usize, 0), -1);
+    println!("default actions ok");
+
+    println!("signal pass.");
+    exit(0)
+}
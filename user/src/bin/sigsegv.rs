@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, mmap, mprotect, sigaction, waitpid_status, SignalAction, WaitStatus, PROT_READ,
+    PROT_WRITE, SIGILL, SIGSEGV, SIGTRAP,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+
+static FAULT_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+// 把出错的页面改成可写，handler返回后经过sigreturn重新执行出错的指令
+extern "C" fn segv_handler(signum: i32, addr: usize) {
+    assert_eq!(signum, SIGSEGV);
+    FAULT_ADDR.store(addr, Ordering::SeqCst);
+    let page = addr & !(PAGE_SIZE - 1);
+    assert_eq!(mprotect(page, PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+}
+
+extern "C" fn ill_handler(signum: i32, addr: usize) {
+    assert_eq!(signum, SIGILL);
+    // 出错地址是非法指令本身
+    exit(if addr != 0 { 0x42 } else { 0 });
+}
+
+fn illegal_instruction() {
+    unsafe { core::arch::asm!("unimp") };
+}
+
+fn wait_child(pid: isize) -> WaitStatus {
+    let mut status = WaitStatus::Exited(0);
+    assert_eq!(waitpid_status(pid as usize, &mut status), pid);
+    status
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // handler修复缺页后程序继续执行
+    let action = SignalAction {
+        handler: segv_handler as *const () as usize,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGSEGV, Some(&action), None), 0);
+    let page = mmap(0, PAGE_SIZE, PROT_READ);
+    assert!(page > 0);
+    let addr = page as usize + 0x10;
+    unsafe { (addr as *mut usize).write_volatile(0x1234) };
+    assert_eq!(FAULT_ADDR.load(Ordering::SeqCst), addr);
+    assert_eq!(unsafe { (addr as *const usize).read_volatile() }, 0x1234);
+    println!("SIGSEGV handled at {:#x}", addr);
+
+    // 没有handler时被SIGSEGV结束，和exit(-SIGSEGV)能区分开
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(sigaction(SIGSEGV, Some(&SignalAction::default()), None), 0);
+        unsafe { (0 as *mut usize).write_volatile(0) };
+        exit(0);
+    }
+    assert_eq!(wait_child(pid), WaitStatus::Signaled(SIGSEGV));
+    let pid = fork();
+    if pid == 0 {
+        exit(-SIGSEGV);
+    }
+    assert_eq!(wait_child(pid), WaitStatus::Exited(-SIGSEGV));
+
+    // 非法指令
+    let pid = fork();
+    if pid == 0 {
+        illegal_instruction();
+        exit(0);
+    }
+    assert_eq!(wait_child(pid), WaitStatus::Signaled(SIGILL));
+    let pid = fork();
+    if pid == 0 {
+        let action = SignalAction {
+            handler: ill_handler as *const () as usize,
+            ..Default::default()
+        };
+        assert_eq!(sigaction(SIGILL, Some(&action), None), 0);
+        illegal_instruction();
+        exit(0);
+    }
+    assert_eq!(wait_child(pid), WaitStatus::Exited(0x42));
+
+    // 断点
+    let pid = fork();
+    if pid == 0 {
+        unsafe { core::arch::asm!("ebreak") };
+        exit(0);
+    }
+    assert_eq!(wait_child(pid), WaitStatus::Signaled(SIGTRAP));
+
+    // handler执行期间再次出错，进程被结束
+    let pid = fork();
+    if pid == 0 {
+        let action = SignalAction {
+            handler: nested_handler as *const () as usize,
+            ..Default::default()
+        };
+        assert_eq!(sigaction(SIGSEGV, Some(&action), None), 0);
+        unsafe { (0 as *mut usize).write_volatile(0) };
+        exit(0);
+    }
+    assert_eq!(wait_child(pid), WaitStatus::Signaled(SIGSEGV));
+    println!("sigsegv passed!");
+    0
+}
+
+extern "C" fn nested_handler(_signum: i32, _addr: usize) {
+    unsafe { (0 as *mut usize).write_volatile(0) };
+}
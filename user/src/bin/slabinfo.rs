@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, slabinfo, waitpid, SlabInfo};
+
+const MAX_CACHES: usize = 16;
+const CHILDREN: usize = 16;
+
+fn print_slabinfo() {
+    let mut buf = [SlabInfo::default(); MAX_CACHES];
+    let total = slabinfo(&mut buf) as usize;
+    println!("{:>6} {:>6} {:>8} {:>8}", "SIZE", "PAGES", "IN_USE", "FREE");
+    for info in buf.iter().take(total.min(MAX_CACHES)) {
+        println!(
+            "{:>6} {:>6} {:>8} {:>8}",
+            info.object_size, info.pages, info.in_use, info.free
+        );
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    print_slabinfo();
+    // 反复创建进程，进程控制块等内核对象都从slab分配
+    let mut pids = [0; CHILDREN];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            exit(0);
+        }
+        assert!(*pid > 0);
+    }
+    let mut exit_code: i32 = 0;
+    for &pid in pids.iter() {
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("after {} forks:", CHILDREN);
+    print_slabinfo();
+    0
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, waitpid_status, WaitStatus, SIGSEGV};
+
+const FRAME_SIZE: usize = 0x1000;
+
+// 每一层递归占用一页多的栈，depth层大约需要depth页
+#[inline(never)]
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; FRAME_SIZE];
+    frame[0] = depth as u8;
+    let frame = core::hint::black_box(&mut frame);
+    if depth == 0 {
+        return frame[0] as usize;
+    }
+    recurse(depth - 1) + frame[0] as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 256K，远超过用户栈初始的8K
+    let depth = 64;
+    assert_eq!(recurse(depth), depth * (depth + 1) / 2);
+    println!("stack grew to {}K", depth * FRAME_SIZE / 1024);
+
+    // 超过用户栈的上限会碰到guard page，子进程被杀掉
+    let pid = fork();
+    if pid == 0 {
+        recurse(usize::MAX);
+        exit(0);
+    }
+    let mut status = WaitStatus::Exited(0);
+    assert_eq!(waitpid_status(pid as usize, &mut status), pid);
+    assert_eq!(status, WaitStatus::Signaled(SIGSEGV));
+    println!("stack pass.");
+    0
+}
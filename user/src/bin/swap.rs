@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+// 比可用的物理内存（不到128M）还大，一部分页面必须换出到交换区
+const SIZE: usize = 0x900_0000; // 144M
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("Test swap start.");
+    let start = mmap(0, SIZE, PROT_READ | PROT_WRITE);
+    assert!(start > 0);
+    let start = start as usize;
+    for (i, page) in (start..start + SIZE).step_by(PAGE_SIZE).enumerate() {
+        unsafe { (page as *mut usize).write_volatile(i) };
+        if (i + 1) % 0x1000 == 0 {
+            println!("write {}M", (i + 1) * PAGE_SIZE / 0x10_0000);
+        }
+    }
+    // 前面写的页面早就被换出去了，再读回来检查内容
+    for (i, page) in (start..start + SIZE).step_by(PAGE_SIZE).enumerate() {
+        assert_eq!(unsafe { (page as *const usize).read_volatile() }, i);
+    }
+    println!("read back ok");
+    assert_eq!(munmap(start, SIZE), 0);
+    println!("swap pass.");
+    0
+}
@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{args, exec, exit, fork, gettid, sleep, thread_create, waitpid, waittid};
+
+fn sleeper() -> ! {
+    loop {
+        sleep(10000);
+    }
+}
+
+fn child_thread() -> ! {
+    exit(5)
+}
+
+// 在非0号线程中fork，子进程中只有这个线程
+fn fork_thread() -> ! {
+    let local = 0x1234usize;
+    let pid = fork();
+    if pid == 0 {
+        // 用户栈的位置不变，它在子进程中成为0号线程
+        assert_eq!(gettid(), 0);
+        assert_eq!(*core::hint::black_box(&local), 0x1234);
+        let tid = thread_create(child_thread as *const () as usize, 0);
+        assert_eq!(waittid(tid as usize), 5);
+        exit(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    exit(0)
+}
+
+// 在非0号线程中exec，其他线程都被结束
+fn exec_thread() -> ! {
+    exec(
+        "thread_fork\0",
+        &["thread_fork\0".as_ptr(), "exec\0".as_ptr()],
+    );
+    unreachable!();
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let argv = args();
+    if argv.len() == 2 {
+        // exec之后只剩下调用exec的线程
+        assert_eq!(argv[1], "exec");
+        assert_eq!(gettid(), 0);
+        return 42;
+    }
+
+    let sleeper_tid = thread_create(sleeper as *const () as usize, 0);
+    assert!(sleeper_tid > 0);
+    let tid = thread_create(fork_thread as *const () as usize, 0);
+    assert_eq!(waittid(tid as usize), 0);
+    println!("fork in thread ok");
+
+    let pid = fork();
+    if pid == 0 {
+        thread_create(sleeper as *const () as usize, 0);
+        let tid = thread_create(exec_thread as *const () as usize, 0);
+        waittid(tid as usize);
+        unreachable!();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 42);
+    println!("thread_fork passed!");
+    // 还在睡眠的线程随主线程一起退出
+    0
+}
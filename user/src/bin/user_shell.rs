@@ -6,6 +6,7 @@ extern crate user_lib;
 extern crate alloc;
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use user_lib::{console::getchar, exec, fork, getpid, print, println, waitpid};
 
 const LF: u8 = 0x0au8; // \n
@@ -24,12 +25,21 @@ pub fn main() -> i32 {
                 // 换行
                 println!("");
                 // 根据输入的命令调用对应的程序
-                if !line.is_empty() {
-                    line.push('\0');
+                if !line.trim().is_empty() {
+                    // 按空格分割出程序名和参数，每个参数都以\0结尾
+                    let args: Vec<String> = line
+                        .split_whitespace()
+                        .map(|arg| {
+                            let mut arg = String::from(arg);
+                            arg.push('\0');
+                            arg
+                        })
+                        .collect();
+                    let args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
                     let pid = fork();
                     if pid == 0 {
                         // 子进程
-                        if exec(line.as_str(), &[]) == -1 {
+                        if exec(args[0].as_str(), &args_addr) < 0 {
                             println!("Error when executing! command = {}", line);
                             return -4;
                         }
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, meminfo, mmap, mprotect, read, waitpid, MemInfo, PROT_READ, PROT_WRITE,
+};
+
+const EFAULT: isize = -14;
+const PAGE_SIZE: usize = 0x1000;
+
+// 不可变的static放在.rodata中，只读
+static RODATA: [u8; 16] = [0x5a; 16];
+static mut DATA: MemInfo = MemInfo {
+    total_frames: 0,
+    free_frames: 0,
+    swap_total_pages: 0,
+    swap_used_pages: 0,
+    kernel_heap_total: 0,
+    kernel_heap_used: 0,
+    zero_page_saved: 0,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 内核不能替用户程序改写.text和.rodata
+    let text = unsafe { core::slice::from_raw_parts_mut(main as *const () as *mut u8, 1) };
+    assert_eq!(read(0, text), EFAULT);
+    let rodata = unsafe { core::slice::from_raw_parts_mut(RODATA.as_ptr() as *mut u8, 1) };
+    assert_eq!(read(0, rodata), EFAULT);
+    let info = unsafe { &mut *(RODATA.as_ptr() as *mut MemInfo) };
+    assert_eq!(meminfo(info), EFAULT);
+    assert!(RODATA.iter().all(|&x| x == 0x5a));
+    println!("text/rodata ok");
+
+    // mprotect成只读之后也不能写
+    let start = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE) as usize;
+    assert_eq!(mprotect(start, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(meminfo(unsafe { &mut *(start as *mut MemInfo) }), EFAULT);
+    let mut exit_code: i32 = 0;
+    let pid = fork();
+    if pid == 0 {
+        exit(3);
+    }
+    assert_eq!(
+        waitpid(pid as usize, unsafe { &mut *(start as *mut i32) }),
+        EFAULT
+    );
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 3);
+    println!("mprotect ok");
+
+    // 写时复制的页面由内核写入时也要复制，不能改到父进程
+    unsafe { DATA.free_frames = 1 };
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(meminfo(unsafe { &mut *core::ptr::addr_of_mut!(DATA) }), 0);
+        assert_ne!(unsafe { DATA.total_frames }, 0);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { DATA.total_frames }, 0);
+    println!("write_protect pass.");
+    0
+}
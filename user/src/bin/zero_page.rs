@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, mmap, munmap, MemInfo, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+const PAGES: usize = 256;
+
+fn info() -> MemInfo {
+    let mut info = MemInfo::default();
+    assert_eq!(meminfo(&mut info), 0);
+    info
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = mmap(0, PAGES * PAGE_SIZE, PROT_READ | PROT_WRITE) as usize;
+    let buf = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, PAGES * PAGE_SIZE) };
+
+    // 只读不写的页面都映射到同一个全0页帧
+    let before = info();
+    for page in 0..PAGES {
+        assert_eq!(
+            unsafe { core::ptr::read_volatile(&buf[page * PAGE_SIZE]) },
+            0
+        );
+    }
+    let after = info();
+    assert_eq!(after.zero_page_saved, before.zero_page_saved + PAGES);
+    assert!(before.free_frames - after.free_frames < PAGES / 2);
+    println!(
+        "read {} pages, {} frames saved",
+        PAGES, after.zero_page_saved
+    );
+
+    // 写的时候才分配私有的页帧，其他页面仍然是0
+    buf[PAGE_SIZE + 1] = 42;
+    assert_eq!(info().zero_page_saved, after.zero_page_saved - 1);
+    assert_eq!(buf[PAGE_SIZE], 0);
+    assert_eq!(buf[PAGE_SIZE + 1], 42);
+    assert_eq!(buf[2 * PAGE_SIZE + 1], 0);
+
+    assert_eq!(munmap(start, PAGES * PAGE_SIZE), 0);
+    assert_eq!(info().zero_page_saved, before.zero_page_saved);
+    println!("zero_page pass.");
+    0
+}
@@ -10,17 +10,31 @@ mod heap;
 mod lang_items;
 mod syscall;
 
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use heap::{HEAP, HEAP_SPACE, USER_HEAP_SIZE};
 
+/// 内核传入的参数个数argc和参数数组argv的地址
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicUsize = AtomicUsize::new(0);
+/// 内核传入的环境变量数组envp的地址
+static ENVP: AtomicUsize = AtomicUsize::new(0);
+
 #[no_mangle]
 // 将_start函数放到.text.entry段中
 // 系统加载后会跳到0x10000执行_start函数
+// 内核通过a0、a1、a2传入argc、argv和envp
 #[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize, envp: usize) -> ! {
     unsafe {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv, Ordering::Relaxed);
+    ENVP.store(envp, Ordering::Relaxed);
     exit(main());
 }
 
@@ -31,6 +45,74 @@ fn main() -> i32 {
     panic!("Cannot find main!");
 }
 
+/// 把内核放在栈上的以0结尾的字符串转换成&str
+unsafe fn c_str(ptr: usize) -> &'static str {
+    let start = ptr as *const u8;
+    let len = (0..).find(|&i| *start.add(i) == 0).unwrap();
+    core::str::from_utf8(core::slice::from_raw_parts(start, len)).unwrap()
+}
+
+/// 命令行参数，第一项是程序名
+pub fn args() -> Vec<&'static str> {
+    let argv = ARGV.load(Ordering::Relaxed) as *const usize;
+    (0..ARGC.load(Ordering::Relaxed))
+        .map(|i| unsafe { c_str(*argv.add(i)) })
+        .collect()
+}
+
+/// 环境变量，每一项的格式为"NAME=value"
+pub fn env() -> Vec<&'static str> {
+    let envp = ENVP.load(Ordering::Relaxed) as *const usize;
+    let mut envs = Vec::new();
+    if envp.is_null() {
+        return envs;
+    }
+    unsafe {
+        while *envp.add(envs.len()) != 0 {
+            envs.push(c_str(*envp.add(envs.len())));
+        }
+    }
+    envs
+}
+
+/// 辅助向量中各项的类型，和Linux一致
+pub const AT_PHDR: usize = 3;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_ENTRY: usize = 9;
+pub const AT_RANDOM: usize = 25;
+
+/// 内核放在栈上的辅助向量中类型为key的值，不存在时返回None
+/// 辅助向量紧跟在envp的结尾之后，由(类型, 值)组成，以AT_NULL结尾
+pub fn getauxval(key: usize) -> Option<usize> {
+    let envp = ENVP.load(Ordering::Relaxed) as *const usize;
+    if envp.is_null() {
+        return None;
+    }
+    unsafe {
+        let mut auxv = envp;
+        while *auxv != 0 {
+            auxv = auxv.add(1);
+        }
+        auxv = auxv.add(1);
+        while *auxv != 0 {
+            if *auxv == key {
+                return Some(*auxv.add(1));
+            }
+            auxv = auxv.add(2);
+        }
+    }
+    None
+}
+
+/// 名为name的环境变量的值
+pub fn getenv(name: &str) -> Option<&'static str> {
+    env().into_iter().find_map(|var| {
+        let (key, value) = var.split_once('=')?;
+        (key == name).then_some(value)
+    })
+}
+
 use syscall::*;
 
 // 对sys_write的二次封装
@@ -57,14 +139,114 @@ pub fn sbrk(size: i32) -> isize {
     sys_sbrk(size)
 }
 
+pub const PROT_READ: usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+pub const PROT_EXEC: usize = 1 << 2;
+
+/// 匿名映射len字节的内存，start为0时由内核选择地址，返回映射的起始地址，失败返回-1
+pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
+    sys_mmap(start, len, prot)
+}
+
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_RMID: usize = 0;
+
+/// 获取key对应的共享内存段，不存在时创建一个len字节的新段，返回共享内存段的id
+pub fn shmget(key: usize, len: usize) -> isize {
+    sys_shmget(key, len)
+}
+
+/// 把共享内存段映射到start处，start为0时由内核选择地址，返回映射的起始地址
+pub fn shmat(id: usize, start: usize) -> isize {
+    sys_shmat(id, start)
+}
+
+pub fn shmdt(start: usize) -> isize {
+    sys_shmdt(start)
+}
+
+pub fn shmctl(id: usize, cmd: usize) -> isize {
+    sys_shmctl(id, cmd)
+}
+
+/// 系统的内存使用情况，页面的单位都是页帧，堆的单位是字节
+#[repr(C)]
+#[derive(Default)]
+pub struct MemInfo {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    pub swap_total_pages: usize,
+    pub swap_used_pages: usize,
+    pub kernel_heap_total: usize,
+    pub kernel_heap_used: usize,
+    pub zero_page_saved: usize,
+}
+
+/// 一个进程的内存使用情况，ppid为0表示没有父进程
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct ProcInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    pub threads: usize,
+    pub resident_pages: usize,
+    pub swapped_pages: usize,
+}
+
+/// 内核一个slab cache的使用情况，pages为cache占用的页帧数
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct SlabInfo {
+    pub object_size: usize,
+    pub pages: usize,
+    pub in_use: usize,
+    pub free: usize,
+}
+
+pub fn meminfo(info: &mut MemInfo) -> isize {
+    sys_meminfo(info)
+}
+
+/// 获取各个进程的内存使用情况，返回进程总数，大于buf.len()说明buf不够大
+pub fn procinfo(buf: &mut [ProcInfo]) -> isize {
+    sys_procinfo(buf)
+}
+
+/// 获取内核各个slab cache的使用情况，返回cache总数，大于buf.len()说明buf不够大
+pub fn slabinfo(buf: &mut [SlabInfo]) -> isize {
+    sys_slabinfo(buf)
+}
+
 pub fn getpid() -> isize {
     sys_getpid()
 }
 pub fn fork() -> isize {
     sys_fork()
 }
+/// 执行path对应的程序，args中的每个参数都要以\0结尾，新程序继承当前的环境变量
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
-    sys_exec(path, args)
+    let envp = ENVP.load(Ordering::Relaxed) as *const *const u8;
+    sys_exec(path, &null_terminated(args), envp)
+}
+
+/// 和exec一样，但新程序的环境变量为envs，每一项的格式为"NAME=value\0"
+pub fn execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    sys_exec(path, &null_terminated(args), null_terminated(envs).as_ptr())
+}
+
+/// 内核要求参数和环境变量数组以空指针结尾
+fn null_terminated(ptrs: &[*const u8]) -> Vec<*const u8> {
+    let mut ptrs = ptrs.to_vec();
+    ptrs.push(core::ptr::null());
+    ptrs
 }
 
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
@@ -74,7 +256,7 @@ pub fn read(fd: usize, buf: &mut [u8]) -> isize {
 /// 等待任意子进程退出，返回子进程的pid，-1表示没有子进程
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
+        match sys_waitpid(-1, exit_code as *mut _, core::ptr::null_mut()) {
             -2 => {
                 yield_();
             }
@@ -87,7 +269,7 @@ pub fn wait(exit_code: &mut i32) -> isize {
 /// 等待子进程pid退出，返回子进程的pid，-1表示没有子进程
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
+        match sys_waitpid(pid as isize, exit_code as *mut _, core::ptr::null_mut()) {
             -2 => {
                 yield_();
             }
@@ -97,6 +279,38 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     }
 }
 
+/// 子进程的结束方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// 调用exit退出，带有退出码
+    Exited(i32),
+    /// 被信号结束，带有信号编号
+    Signaled(i32),
+}
+
+/// 和waitpid一样等待子进程pid退出，同时区分子进程是正常退出还是被信号结束
+pub fn waitpid_status(pid: usize, status: &mut WaitStatus) -> isize {
+    let mut exit_code: i32 = 0;
+    let mut signal: i32 = 0;
+    loop {
+        match sys_waitpid(pid as isize, &mut exit_code, &mut signal) {
+            -2 => {
+                yield_();
+            }
+            exit_pid => {
+                if exit_pid > 0 {
+                    *status = if signal != 0 {
+                        WaitStatus::Signaled(signal)
+                    } else {
+                        WaitStatus::Exited(exit_code)
+                    };
+                }
+                return exit_pid;
+            }
+        }
+    }
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
@@ -147,4 +361,86 @@ pub fn condvar_signal(condvar_id: usize) {
 }
 pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
     sys_condvar_wait(condvar_id, mutex_id);
-}
\ No newline at end of file
+}
+
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+
+/// 执行默认动作
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+/// 信号对应的屏蔽位，用于sigprocmask和SignalAction::mask
+pub const fn sigmask(signum: i32) -> u32 {
+    1 << signum
+}
+
+/// 信号的处理方式，handler为SIG_DFL、SIG_IGN或者处理函数的地址，
+/// 处理函数是`extern "C" fn(signum: i32, addr: usize)`，参数是信号编号和出错的地址
+/// （只有SIGSEGV/SIGBUS/SIGILL这些硬件异常才有）
+/// mask为handler执行期间额外屏蔽的信号
+/// restorer是handler返回的地址，它要调用sigreturn，为0时sigaction会填入sigreturn_trampoline
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub mask: u32,
+    pub restorer: usize,
+}
+
+pub fn kill(pid: usize, signum: i32) -> isize {
+    sys_kill(pid, signum)
+}
+
+/// 设置信号signum的处理方式，old_action不为None时返回原来的处理方式
+pub fn sigaction(
+    signum: i32,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    let action = action.map(|a| {
+        let mut a = *a;
+        if a.restorer == 0 {
+            a.restorer = sigreturn_trampoline as *const () as usize;
+        }
+        a
+    });
+    sys_sigaction(
+        signum,
+        action.as_ref().map_or(core::ptr::null(), |a| a as *const _),
+        old_action.map_or(core::ptr::null_mut(), |a| a as *mut _),
+    )
+}
+
+/// 信号handler默认的返回地址，调用sigreturn恢复执行handler之前的现场，不会返回
+pub extern "C" fn sigreturn_trampoline() -> ! {
+    sys_sigreturn();
+    unreachable!();
+}
+
+/// 设置屏蔽的信号集合，返回原来的集合
+pub fn sigprocmask(mask: u32) -> isize {
+    sys_sigprocmask(mask)
+}
+
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}